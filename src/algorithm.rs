@@ -1,4 +1,3 @@
-use crate::evolution::create_empty_board;
 use crate::model::{Board, Cell, Pos};
 
 #[derive(Clone)]
@@ -17,31 +16,29 @@ impl Algorithm {
     }
 }
 
-fn rearrange_overlaps(cells: &Vec<Cell>) -> Vec<Cell> {
-    let mut board = create_empty_board();
+pub fn rearrange_overlaps(cells: &Vec<Cell>) -> Vec<Cell> {
+    let mut board = Board::default();
     let mut cells = cells.clone();
     for index in 0..cells.len() {
         let cell = &cells[index];
-        if board.cells[cell.pos.x][cell.pos.y].is_some() {
-            let closest_free_pos = &find_closest_free_pos(&mut board, &cell.pos);
+        if board.cells.contains_key(&cell.pos) {
+            let closest_free_pos = find_closest_free_pos(&board, &cell.pos);
             cells[index] = Cell {
-                pos: closest_free_pos.clone(),
+                pos: closest_free_pos,
                 card: cell.card.clone(),
                 card_side: cell.card_side.clone(),
             };
-            board.cells[closest_free_pos.x][closest_free_pos.y] = Some(cells[index].clone());
-        } else {
-            board.cells[cell.pos.x][cell.pos.y] = Some(cell.clone());
         }
+        board.insert(cells[index].clone());
     }
     cells
 }
 
-fn find_closest_free_pos(board: &mut Board, pos: &Pos) -> Pos {
+fn find_closest_free_pos(board: &Board, pos: &Pos) -> Pos {
     let mut free_cell: Option<Pos> = None;
     let mut distance = 1;
     while free_cell.is_none() {
-        free_cell = get_free_cell(board, &get_halo(board, pos, distance));
+        free_cell = get_free_cell(board, &get_halo(pos, distance));
         distance += 1;
     }
     free_cell.unwrap()
@@ -50,26 +47,31 @@ fn find_closest_free_pos(board: &mut Board, pos: &Pos) -> Pos {
 fn get_free_cell(board: &Board, positions: &Vec<Pos>) -> Option<Pos> {
     positions
         .iter()
-        .find(|pos| board.cells[pos.x][pos.y].is_none())
+        .find(|pos| !board.cells.contains_key(pos))
         .cloned()
 }
 
-fn get_halo(board: &Board, pos: &Pos, distance: usize) -> Vec<Pos> {
-    let mut result: Vec<(i32, i32)> = vec![];
-    for x in pos.x as i32 - distance as i32..pos.x as i32 + distance as i32 {
-        result.push((x as i32, pos.y as i32 - distance as i32));
-        result.push((x as i32, pos.y as i32 + distance as i32));
+fn get_halo(pos: &Pos, distance: i32) -> Vec<Pos> {
+    let mut result: Vec<Pos> = vec![];
+    for x in pos.x - distance..pos.x + distance {
+        result.push(Pos {
+            x,
+            y: pos.y - distance,
+        });
+        result.push(Pos {
+            x,
+            y: pos.y + distance,
+        });
     }
-    for y in pos.y as i32 - distance as i32..pos.y as i32 + distance as i32 {
-        result.push((pos.x as i32 - distance as i32, y as i32));
-        result.push((pos.x as i32 + distance as i32, y as i32));
+    for y in pos.y - distance..pos.y + distance {
+        result.push(Pos {
+            x: pos.x - distance,
+            y,
+        });
+        result.push(Pos {
+            x: pos.x + distance,
+            y,
+        });
     }
     result
-        .into_iter()
-        .filter(|(x, y)| *x >= 0 && *y >= 0 && *x < board.width as i32 && *y < board.height as i32)
-        .map(|(x, y)| Pos {
-            x: x as usize,
-            y: y as usize,
-        })
-        .collect()
 }
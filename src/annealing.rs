@@ -0,0 +1,92 @@
+use crate::algorithm::Algorithm;
+use crate::carcassone::fill_board;
+use crate::config::EvolutionConfig;
+use crate::evolution::{generate_algorithm, seeded_rng, EvolutionRng};
+use crate::fitness::{Evaluator, Fitness};
+use crate::model::Card;
+use crate::mutation::{mutations, WeightedMutation};
+use crate::solver::{RatedBoard, Solver};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+/// Improves a single algorithm by applying one mutation at a time. A worse neighbour is still
+/// accepted with a probability that shrinks as the temperature cools down after every move.
+/// Each generation makes as many moves as the evolution rates algorithms in a generation, so
+/// both solvers can be compared on the same seeds.
+pub struct Annealing<'a> {
+    config: &'a EvolutionConfig,
+    evaluator: Evaluator,
+    mutations: Vec<WeightedMutation>,
+    choice: WeightedIndex<f64>,
+    rng: EvolutionRng,
+    current: (Fitness, Algorithm),
+    best: (Fitness, Algorithm),
+    temperature: f64,
+    generation: usize,
+}
+
+impl<'a> Annealing<'a> {
+    pub fn new(config: &'a EvolutionConfig, cards: &'a Vec<Card>) -> Annealing<'a> {
+        let mut rng = seeded_rng(config);
+        let evaluator = Evaluator::new(&config.weights, cards);
+        let mutations = mutations(&config.mutation_rates);
+        let choice =
+            WeightedIndex::new(mutations.iter().map(|mutation| mutation.probability)).unwrap();
+        let algorithm = generate_algorithm(config, &mut rng, cards);
+        let fitness = evaluator.evaluate_cells(&algorithm.arranged_cells);
+        Annealing {
+            config,
+            evaluator,
+            mutations,
+            choice,
+            rng,
+            current: (fitness.clone(), algorithm.clone()),
+            best: (fitness, algorithm),
+            temperature: config.initial_temperature,
+            generation: 0,
+        }
+    }
+
+    fn make_move(&mut self) {
+        let config = self.config;
+        let mut cells = self.current.1.cells.clone();
+        let mutation = &self.mutations[self.choice.sample(&mut self.rng)];
+        mutation.mutation.mutate(config, &mut self.rng, &mut cells);
+        let neighbour = Algorithm::new(cells);
+        let fitness = self.evaluator.evaluate_cells(&neighbour.arranged_cells);
+        let delta = fitness.score as f64 - self.current.0.score as f64;
+        if delta <= 0.0 || self.rng.gen_range(0.0, 1.0) < (-delta / self.temperature).exp() {
+            if fitness.score < self.best.0.score {
+                self.best = (fitness.clone(), neighbour.clone());
+            }
+            self.current = (fitness, neighbour);
+        }
+        self.temperature *= config.cooling_rate;
+    }
+}
+
+impl Solver for Annealing<'_> {
+    fn step(&mut self) -> RatedBoard {
+        for _ in 0..self.config.population_size {
+            self.make_move();
+        }
+        self.generation += 1;
+        self.best().unwrap()
+    }
+
+    fn best(&self) -> Option<RatedBoard> {
+        Some(RatedBoard {
+            generation: self.generation.saturating_sub(1),
+            fitness: self.best.0.clone(),
+            board: fill_board(&self.best.1.arranged_cells),
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        let out_of_generations = self
+            .config
+            .max_generations
+            .map_or(false, |max_generations| self.generation >= max_generations);
+        self.best.0.score == 0 || out_of_generations
+    }
+}
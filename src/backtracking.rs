@@ -0,0 +1,403 @@
+use crate::config::EvolutionConfig;
+use crate::fitness::Evaluator;
+use crate::model::{Board, Card, CardSide, Cell, Pos, TerrainType};
+use crate::solver::{RatedBoard, Solver};
+
+const SIDES: [CardSide; 4] = [
+    CardSide::LEFT,
+    CardSide::TOP,
+    CardSide::RIGHT,
+    CardSide::BOTTOM,
+];
+/// Tiles placed in each generation.
+const STEPS_PER_GENERATION: usize = 1000;
+
+/// A distinct way to lay down one kind of card.
+struct Orientation {
+    kind: usize,
+    card_side: CardSide,
+    sides: [TerrainType; 4],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    Empty,
+    /// Left empty on purpose by the current branch of the search.
+    Blocked,
+    Tile(usize),
+}
+
+/// A position being filled: the cards that fit it, how many of them have been tried and
+/// whether the search has moved on to leaving it empty.
+struct Frame {
+    index: usize,
+    candidates: Vec<usize>,
+    tried: usize,
+    placed: Option<usize>,
+    blocked: bool,
+}
+
+/// Places cards one at a time so that every edge matches its neighbours and no town stays
+/// open, apart from one edge when the cards have an odd number of town edges. Positions next
+/// to the placed tiles are filled in the order of how few cards fit them, and after trying
+/// every card at a position the search also tries leaving it empty, as long as that does not
+/// open more town edges than allowed. The grid reaches as far from the first tile as there are
+/// cards, so the search is not limited by `board_size` and finds a single cluster of all cards
+/// whenever one exists. Each generation places up to 1000 tiles.
+pub struct Backtracking<'a> {
+    config: &'a EvolutionConfig,
+    cards: &'a Vec<Card>,
+    evaluator: Evaluator,
+    kinds: Vec<Card>,
+    remaining: Vec<usize>,
+    orientations: Vec<Orientation>,
+    size: usize,
+    slots: Vec<Slot>,
+    /// Placed tiles in every column and row, to find the part of the grid in use.
+    column_tiles: Vec<usize>,
+    row_tiles: Vec<usize>,
+    stack: Vec<Frame>,
+    placed: usize,
+    steps: usize,
+    generation: usize,
+    deepest: Option<(usize, Vec<Slot>)>,
+    /// Town edges that must stay open because the cards have an odd number of them.
+    allowed_open: usize,
+    /// Town edges facing positions left empty on purpose.
+    blocked_town_edges: usize,
+    solved: bool,
+}
+
+impl<'a> Backtracking<'a> {
+    pub fn new(config: &'a EvolutionConfig, cards: &'a Vec<Card>) -> Backtracking<'a> {
+        let mut kinds: Vec<Card> = vec![];
+        let mut remaining = vec![];
+        for card in cards {
+            match kinds.iter().position(|kind| kind == card) {
+                Some(kind) => remaining[kind] += 1,
+                None => {
+                    kinds.push(card.clone());
+                    remaining.push(1);
+                }
+            }
+        }
+        let mut orientations: Vec<Orientation> = vec![];
+        for (kind, card) in kinds.iter().enumerate() {
+            for card_side in SIDES.iter() {
+                let cell = Cell {
+                    pos: Pos { x: 0, y: 0 },
+                    card: card.clone(),
+                    card_side: card_side.clone(),
+                };
+                let sides = [cell.left(), cell.top(), cell.right(), cell.bottom()];
+                // Symmetric cards look the same in several rotations.
+                let duplicate = orientations
+                    .iter()
+                    .any(|orientation| orientation.kind == kind && orientation.sides == sides);
+                if !duplicate {
+                    orientations.push(Orientation {
+                        kind,
+                        card_side: card_side.clone(),
+                        sides,
+                    });
+                }
+            }
+        }
+        let town_edges: usize = cards
+            .iter()
+            .map(|card| {
+                SIDES
+                    .iter()
+                    .filter(|side| card.get_terrain((*side).clone()) == TerrainType::TOWN)
+                    .count()
+            })
+            .sum();
+        // No tile of a connected board is further than `cards.len() - 1` positions from the
+        // first one, which starts in the middle, so there are always two free rows and columns
+        // between the tiles and the edge of the grid.
+        let size = 2 * cards.len() + 3;
+        let mut search = Backtracking {
+            config,
+            cards,
+            evaluator: Evaluator::new(&config.weights, cards),
+            kinds,
+            remaining,
+            orientations,
+            size,
+            slots: vec![Slot::Empty; size * size],
+            column_tiles: vec![0; size],
+            row_tiles: vec![0; size],
+            stack: vec![],
+            placed: 0,
+            steps: 0,
+            generation: 0,
+            deepest: None,
+            allowed_open: town_edges % 2,
+            blocked_town_edges: 0,
+            solved: false,
+        };
+        search.push((size / 2) * size + size / 2);
+        search
+    }
+
+    fn push(&mut self, index: usize) {
+        let candidates = self.candidates(index);
+        self.stack.push(Frame {
+            index,
+            candidates,
+            tried: 0,
+            placed: None,
+            blocked: false,
+        });
+    }
+
+    /// Takes the next branch of the search: the next card at the deepest position, then
+    /// leaving that position empty, then going back to the position before.
+    fn advance(&mut self) {
+        let frame = self.stack.last_mut().unwrap();
+        let index = frame.index;
+        if let Some(orientation) = frame.placed.take() {
+            self.remove(index, orientation);
+        }
+        let frame = self.stack.last_mut().unwrap();
+        let blocked = frame.blocked;
+        if frame.tried < frame.candidates.len() {
+            let orientation = frame.candidates[frame.tried];
+            frame.tried += 1;
+            frame.placed = Some(orientation);
+            self.place(index, orientation);
+            if self.placed == self.cards.len() && self.open_town_edges() <= self.allowed_open {
+                self.solved = true;
+            } else if self.is_promising() {
+                if let Some(next) = self.most_constrained() {
+                    self.push(next);
+                }
+            }
+        } else if !blocked
+            && self.blocked_town_edges + self.town_edges_facing(index) <= self.allowed_open
+        {
+            self.stack.last_mut().unwrap().blocked = true;
+            self.blocked_town_edges += self.town_edges_facing(index);
+            self.slots[index] = Slot::Blocked;
+            if self.is_promising() {
+                if let Some(next) = self.most_constrained() {
+                    self.push(next);
+                }
+            }
+        } else {
+            if blocked {
+                self.slots[index] = Slot::Empty;
+                self.blocked_town_edges -= self.town_edges_facing(index);
+            }
+            self.stack.pop();
+        }
+    }
+
+    fn candidates(&self, index: usize) -> Vec<usize> {
+        (0..self.orientations.len())
+            .filter(|orientation| self.fits(index, *orientation))
+            .collect()
+    }
+
+    fn fits(&self, index: usize, orientation: usize) -> bool {
+        let orientation = &self.orientations[orientation];
+        if self.remaining[orientation.kind] == 0 {
+            return false;
+        }
+        let matching = (0..4).all(|side| match self.slots[self.neighbour(index, side)] {
+            Slot::Tile(other) => {
+                self.orientations[other].sides[(side + 2) % 4] == orientation.sides[side]
+            }
+            _ => true,
+        });
+        matching
+            && self.blocked_town_edges + self.opened_town_edges(index, orientation)
+                <= self.allowed_open
+    }
+
+    /// Town edges of a tile placed at `index` that face positions left empty on purpose.
+    fn opened_town_edges(&self, index: usize, orientation: &Orientation) -> usize {
+        (0..4)
+            .filter(|side| orientation.sides[*side] == TerrainType::TOWN)
+            .filter(|side| self.slots[self.neighbour(index, *side)] == Slot::Blocked)
+            .count()
+    }
+
+    fn place(&mut self, index: usize, orientation: usize) {
+        self.blocked_town_edges += self.opened_town_edges(index, &self.orientations[orientation]);
+        self.slots[index] = Slot::Tile(orientation);
+        self.column_tiles[index / self.size] += 1;
+        self.row_tiles[index % self.size] += 1;
+        self.remaining[self.orientations[orientation].kind] -= 1;
+        self.placed += 1;
+        self.steps += 1;
+        if self
+            .deepest
+            .as_ref()
+            .map_or(true, |(placed, _)| self.placed > *placed)
+        {
+            self.deepest = Some((self.placed, self.slots.clone()));
+        }
+    }
+
+    fn remove(&mut self, index: usize, orientation: usize) {
+        self.slots[index] = Slot::Empty;
+        self.column_tiles[index / self.size] -= 1;
+        self.row_tiles[index % self.size] -= 1;
+        self.blocked_town_edges -= self.opened_town_edges(index, &self.orientations[orientation]);
+        self.remaining[self.orientations[orientation].kind] += 1;
+        self.placed -= 1;
+    }
+
+    /// Every open town edge that may not stay open needs a town card of its own, and one that
+    /// fits.
+    fn is_promising(&self) -> bool {
+        let allowed_open = self.allowed_open - self.blocked_town_edges;
+        let must_fill = self.must_fill();
+        let town_cards: usize = (0..self.kinds.len())
+            .filter(|kind| {
+                self.orientations.iter().any(|orientation| {
+                    orientation.kind == *kind && orientation.sides.contains(&TerrainType::TOWN)
+                })
+            })
+            .map(|kind| self.remaining[kind])
+            .sum();
+        let unfillable = must_fill
+            .iter()
+            .filter(|index| self.candidates(**index).is_empty())
+            .count();
+        must_fill.len() <= town_cards + allowed_open && unfillable <= allowed_open
+    }
+
+    fn open_town_edges(&self) -> usize {
+        let empty_town_edges: usize = self
+            .region()
+            .filter(|index| self.slots[*index] == Slot::Empty)
+            .map(|index| self.town_edges_facing(index))
+            .sum();
+        empty_town_edges + self.blocked_town_edges
+    }
+
+    /// Empty positions next to a town edge of a placed tile.
+    fn must_fill(&self) -> Vec<usize> {
+        self.region()
+            .filter(|index| self.slots[*index] == Slot::Empty)
+            .filter(|index| self.town_edges_facing(*index) > 0)
+            .collect()
+    }
+
+    fn town_edges_facing(&self, index: usize) -> usize {
+        (0..4)
+            .filter(|side| match self.slots[self.neighbour(index, *side)] {
+                Slot::Tile(other) => {
+                    self.orientations[other].sides[(side + 2) % 4] == TerrainType::TOWN
+                }
+                _ => false,
+            })
+            .count()
+    }
+
+    /// The positions of the rectangle around the placed tiles and of the border around it.
+    fn region(&self) -> impl Iterator<Item = usize> {
+        let used = |tiles: &Vec<usize>| {
+            let first = tiles.iter().position(|count| *count > 0)?;
+            let last = tiles.iter().rposition(|count| *count > 0)?;
+            Some(first - 1..=last + 1)
+        };
+        let size = self.size;
+        let columns = used(&self.column_tiles).unwrap_or(1..=0);
+        let rows = used(&self.row_tiles).unwrap_or(1..=0);
+        columns.flat_map(move |x| rows.clone().map(move |y| x * size + y))
+    }
+
+    /// The empty position next to the placed tiles that the fewest cards fit. Positions no
+    /// card fits stay empty.
+    fn most_constrained(&mut self) -> Option<usize> {
+        let must_fill = self.must_fill();
+        self.region()
+            .filter(|index| self.slots[*index] == Slot::Empty && self.touches_tile(*index))
+            .map(|index| {
+                (
+                    !must_fill.contains(&index),
+                    self.candidates(index).len(),
+                    index,
+                )
+            })
+            .filter(|(_, candidates, _)| *candidates > 0)
+            .min()
+            .map(|(_, _, index)| index)
+    }
+
+    fn touches_tile(&self, index: usize) -> bool {
+        (0..4).any(|side| match self.slots[self.neighbour(index, side)] {
+            Slot::Tile(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Only called for positions at most one step away from the placed tiles, which never lie
+    /// on the edge of the grid.
+    fn neighbour(&self, index: usize, side: usize) -> usize {
+        match side {
+            0 => index - self.size,
+            1 => index - 1,
+            2 => index + self.size,
+            _ => index + 1,
+        }
+    }
+
+    fn rate(&self, slots: &Vec<Slot>) -> RatedBoard {
+        let board = self.to_board(slots);
+        RatedBoard {
+            generation: self.generation.saturating_sub(1),
+            fitness: self.evaluator.evaluate_board(self.cards, &board).unwrap(),
+            board,
+        }
+    }
+
+    fn to_board(&self, slots: &Vec<Slot>) -> Board {
+        let size = self.size;
+        let mut board = Board::default();
+        for (index, slot) in slots.iter().enumerate() {
+            if let Slot::Tile(orientation) = slot {
+                let orientation = &self.orientations[*orientation];
+                board.insert(Cell {
+                    pos: Pos {
+                        x: (index / size) as i32,
+                        y: (index % size) as i32,
+                    },
+                    card: self.kinds[orientation.kind].clone(),
+                    card_side: orientation.card_side.clone(),
+                });
+            }
+        }
+        board
+    }
+}
+
+impl Solver for Backtracking<'_> {
+    fn step(&mut self) -> RatedBoard {
+        let steps = (self.generation + 1) * STEPS_PER_GENERATION;
+        while !self.solved && !self.stack.is_empty() && self.steps < steps {
+            self.advance();
+        }
+        self.generation += 1;
+        self.best().unwrap()
+    }
+
+    fn best(&self) -> Option<RatedBoard> {
+        let slots = match &self.deepest {
+            Some((_, deepest)) if !self.solved => deepest,
+            _ => &self.slots,
+        };
+        Some(self.rate(slots))
+    }
+
+    fn is_done(&self) -> bool {
+        let out_of_generations = self
+            .config
+            .max_generations
+            .map_or(false, |max_generations| self.generation >= max_generations);
+        self.solved || self.stack.is_empty() || out_of_generations
+    }
+}
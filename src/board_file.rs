@@ -0,0 +1,123 @@
+use crate::model::{load_cards, Board, Card, CardSide, Cell, Pos};
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Version 1 also stored the width and height of the board, which are ignored now. Versions 1
+/// and 2 stored the card set path relative to the working directory.
+pub const BOARD_FILE_VERSION: u32 = 3;
+
+/// On-disk representation of a board. Tiles refer to cards by their index in the card set
+/// stored at `cards`, relative to the directory of the board file, and `rotation` is the side
+/// of the card that faces left.
+#[derive(Serialize, Deserialize)]
+struct BoardFile {
+    version: u32,
+    cards: PathBuf,
+    tiles: Vec<Tile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tile {
+    card: usize,
+    rotation: CardSide,
+    x: i32,
+    y: i32,
+}
+
+pub fn save_board(
+    path: &Path,
+    board: &Board,
+    cards_path: &Path,
+    cards: &Vec<Card>,
+) -> Result<(), String> {
+    let mut used_cards = vec![false; cards.len()];
+    let mut tiles = vec![];
+    for cell in board.cells.values() {
+        let card = (0..cards.len())
+            .find(|index| !used_cards[*index] && cards[*index] == cell.card)
+            .ok_or_else(|| format!("card {} is not in {}", cell.card.pic, cards_path.display()))?;
+        used_cards[card] = true;
+        tiles.push(Tile {
+            card,
+            rotation: cell.card_side.clone(),
+            x: cell.pos.x,
+            y: cell.pos.y,
+        });
+    }
+    let board_file = BoardFile {
+        version: BOARD_FILE_VERSION,
+        cards: relative_cards_path(path, cards_path)?,
+        tiles,
+    };
+    let file = std::fs::File::create(path)
+        .map_err(|error| format!("cannot create {}: {}", path.display(), error))?;
+    serde_json::to_writer_pretty(file, &board_file)
+        .map_err(|error| format!("cannot write {}: {}", path.display(), error))
+}
+
+/// Loads a board together with the path of its card set and the cards it is made of.
+pub fn load_board(path: &Path) -> Result<(PathBuf, Vec<Card>, Board), String> {
+    let file = std::fs::File::open(path)
+        .map_err(|error| format!("cannot open {}: {}", path.display(), error))?;
+    let board_file: BoardFile = serde_json::from_reader(file)
+        .map_err(|error| format!("cannot parse {}: {}", path.display(), error))?;
+    if board_file.version == 0 || board_file.version > BOARD_FILE_VERSION {
+        return Err(format!(
+            "{} has version {}, only versions up to {} are supported",
+            path.display(),
+            board_file.version,
+            BOARD_FILE_VERSION
+        ));
+    }
+    let cards_path = if board_file.version < 3 {
+        board_file.cards
+    } else {
+        board_directory(path).join(&board_file.cards)
+    };
+    let cards = load_cards(&cards_path)?;
+    let mut used_cards = vec![false; cards.len()];
+    let mut board = Board::default();
+    for tile in &board_file.tiles {
+        let card = cards
+            .get(tile.card)
+            .ok_or_else(|| format!("{} has no card {}", cards_path.display(), tile.card))?;
+        if used_cards[tile.card] {
+            return Err(format!("card {} is placed more than once", tile.card));
+        }
+        used_cards[tile.card] = true;
+        if board.get(tile.x, tile.y).is_some() {
+            return Err(format!("more than one tile at ({}, {})", tile.x, tile.y));
+        }
+        board.insert(Cell {
+            pos: Pos {
+                x: tile.x,
+                y: tile.y,
+            },
+            card: card.clone(),
+            card_side: tile.rotation.clone(),
+        });
+    }
+    Ok((cards_path, cards, board))
+}
+
+/// The card set path as seen from the directory of the board file, or an absolute path if the
+/// card set lies outside of that directory.
+fn relative_cards_path(path: &Path, cards_path: &Path) -> Result<PathBuf, String> {
+    let cards_path = cards_path
+        .canonicalize()
+        .map_err(|error| format!("cannot open {}: {}", cards_path.display(), error))?;
+    let directory = board_directory(path);
+    let directory = directory
+        .canonicalize()
+        .map_err(|error| format!("cannot open {}: {}", directory.display(), error))?;
+    Ok(cards_path
+        .strip_prefix(&directory)
+        .map_or(cards_path.clone(), Path::to_path_buf))
+}
+
+fn board_directory(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    }
+}
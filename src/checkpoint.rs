@@ -0,0 +1,158 @@
+use crate::algorithm::Algorithm;
+use crate::config::EvolutionConfig;
+use crate::evolution::{EvolutionRng, EvolutionState, HallOfFame, Stagnation};
+use crate::fitness::Evaluator;
+use crate::model::{load_cards, Card, CardSide, Cell, Pos};
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// On-disk representation of a running evolution. Every algorithm lists one placement per
+/// card, in the order of the card set stored at `cards`.
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    version: u32,
+    cards: PathBuf,
+    config: EvolutionConfig,
+    generation: usize,
+    rng: EvolutionRng,
+    population: Vec<Vec<Placement>>,
+    #[serde(default)]
+    hall_of_fame: Option<Vec<Placement>>,
+    #[serde(default)]
+    stagnation: Stagnation,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Placement {
+    rotation: CardSide,
+    x: i32,
+    y: i32,
+}
+
+pub struct Checkpoint {
+    pub cards_path: PathBuf,
+    pub cards: Vec<Card>,
+    pub config: EvolutionConfig,
+    pub state: EvolutionState,
+}
+
+pub fn save_checkpoint(
+    path: &Path,
+    config: &EvolutionConfig,
+    state: &EvolutionState,
+    cards_path: &Path,
+) -> Result<(), String> {
+    let population = state.population.iter().map(to_placements).collect();
+    let hall_of_fame = state
+        .hall_of_fame
+        .as_ref()
+        .map(|best| to_placements(&best.algorithm));
+    let checkpoint_file = CheckpointFile {
+        version: CHECKPOINT_VERSION,
+        cards: cards_path.to_path_buf(),
+        config: config.clone(),
+        generation: state.generation,
+        rng: state.rng.clone(),
+        population,
+        hall_of_fame,
+        stagnation: state.stagnation.clone(),
+    };
+    // Write next to the previous checkpoint first so that an interrupted write never
+    // destroys it.
+    let temp_path = path.with_extension("tmp");
+    let file = std::fs::File::create(&temp_path)
+        .map_err(|error| format!("cannot create {}: {}", temp_path.display(), error))?;
+    serde_json::to_writer(file, &checkpoint_file)
+        .map_err(|error| format!("cannot write {}: {}", temp_path.display(), error))?;
+    std::fs::rename(&temp_path, path)
+        .map_err(|error| format!("cannot write {}: {}", path.display(), error))
+}
+
+pub fn load_checkpoint(path: &Path) -> Result<Checkpoint, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|error| format!("cannot open {}: {}", path.display(), error))?;
+    let checkpoint_file: CheckpointFile = serde_json::from_reader(file)
+        .map_err(|error| format!("cannot parse {}: {}", path.display(), error))?;
+    if checkpoint_file.version != CHECKPOINT_VERSION {
+        return Err(format!(
+            "{} has version {}, only version {} is supported",
+            path.display(),
+            checkpoint_file.version,
+            CHECKPOINT_VERSION
+        ));
+    }
+    let cards = load_cards(&checkpoint_file.cards)?;
+    let config = checkpoint_file.config.upgraded();
+    let mut population = vec![];
+    for placements in checkpoint_file.population {
+        population.push(from_placements(path, &cards, placements)?);
+    }
+    if population.len() < 2 {
+        return Err(format!("{} has fewer than 2 algorithms", path.display()));
+    }
+    let hall_of_fame = match checkpoint_file.hall_of_fame {
+        Some(placements) => {
+            let algorithm = from_placements(path, &cards, placements)?;
+            Some(HallOfFame {
+                fitness: Evaluator::new(&config.weights, &cards)
+                    .evaluate_cells(&algorithm.arranged_cells),
+                algorithm,
+            })
+        }
+        None => None,
+    };
+    let state = EvolutionState {
+        generation: checkpoint_file.generation,
+        rng: checkpoint_file.rng,
+        population,
+        hall_of_fame,
+        stagnation: checkpoint_file.stagnation,
+    };
+    Ok(Checkpoint {
+        cards_path: checkpoint_file.cards,
+        cards,
+        config,
+        state,
+    })
+}
+
+fn to_placements(algorithm: &Algorithm) -> Vec<Placement> {
+    algorithm
+        .cells
+        .iter()
+        .map(|cell| Placement {
+            rotation: cell.card_side.clone(),
+            x: cell.pos.x,
+            y: cell.pos.y,
+        })
+        .collect()
+}
+
+fn from_placements(
+    path: &Path,
+    cards: &Vec<Card>,
+    placements: Vec<Placement>,
+) -> Result<Algorithm, String> {
+    if placements.len() != cards.len() {
+        return Err(format!(
+            "{} places {} cards instead of {}",
+            path.display(),
+            placements.len(),
+            cards.len()
+        ));
+    }
+    let mut cells = vec![];
+    for (card, placement) in cards.iter().zip(placements) {
+        cells.push(Cell {
+            pos: Pos {
+                x: placement.x,
+                y: placement.y,
+            },
+            card: card.clone(),
+            card_side: placement.rotation,
+        });
+    }
+    Ok(Algorithm::new(cells))
+}
@@ -0,0 +1,255 @@
+use crate::config::EvolutionConfig;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const DEFAULT_BOARD_OUTPUT: &str = "./board.json";
+const DEFAULT_IMAGE_OUTPUT: &str = "./board.png";
+const DEFAULT_CNF_OUTPUT: &str = "./board.cnf";
+
+pub enum Command {
+    Gui {
+        config: EvolutionConfig,
+        board: Option<PathBuf>,
+    },
+    Headless {
+        config: EvolutionConfig,
+        output: PathBuf,
+        checkpoint: Option<PathBuf>,
+    },
+    /// A headless run continued from the checkpoint at `resume`, whose config is only known
+    /// once the checkpoint is loaded.
+    Resume {
+        resume: PathBuf,
+        options: ConfigOptions,
+        output: PathBuf,
+        checkpoint: PathBuf,
+    },
+    Export {
+        board: PathBuf,
+        output: PathBuf,
+    },
+    Dimacs {
+        config: EvolutionConfig,
+        output: PathBuf,
+    },
+}
+
+/// Flags that change evolution parameters, in the order they were given.
+pub struct ConfigOptions(Vec<(String, String)>);
+
+impl ConfigOptions {
+    pub fn apply(&self, mut config: EvolutionConfig) -> Result<EvolutionConfig, String> {
+        for (option, value) in &self.0 {
+            set_config_option(&mut config, option, value)?;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Gui,
+    Headless,
+    Export,
+    Dimacs,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let (mode, options) = match args.get(1).map(|arg| arg.as_str()) {
+        Some("headless") => (Mode::Headless, &args[2..]),
+        Some("export") => (Mode::Export, &args[2..]),
+        Some("dimacs") => (Mode::Dimacs, &args[2..]),
+        _ => (Mode::Gui, &args[1..]),
+    };
+    let resume = match find_option(options, "--resume")? {
+        Some(_) if mode != Mode::Headless => return Err("unknown option: --resume".to_owned()),
+        resume => resume.map(PathBuf::from),
+    };
+    let config = match (find_option(options, "--config")?, &resume) {
+        (Some(_), Some(_)) => return Err("--resume cannot be combined with --config".to_owned()),
+        (Some(path), None) => EvolutionConfig::load(Path::new(path))?,
+        (None, _) => EvolutionConfig::default(),
+    };
+    let mut output = None;
+    let mut board = None;
+    let mut checkpoint = resume.clone();
+    let mut config_options = vec![];
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = next_value(&mut options, option)?;
+        match option.as_str() {
+            "--config" | "--resume" => {}
+            "--output" if mode != Mode::Gui => output = Some(PathBuf::from(value)),
+            "--open" if mode == Mode::Gui || mode == Mode::Export => {
+                board = Some(PathBuf::from(value))
+            }
+            "--checkpoint" if mode == Mode::Headless => checkpoint = Some(PathBuf::from(value)),
+            // The board and the random number generator are restored from the checkpoint.
+            "--board-size" | "--seed" if resume.is_some() => {
+                return Err(format!("{} cannot be changed when resuming", option))
+            }
+            _ => config_options.push((option.clone(), value.clone())),
+        }
+    }
+    let options = ConfigOptions(config_options);
+    // Flags given when resuming are checked here already, on top of the default config.
+    let config = options.apply(config)?;
+    match mode {
+        Mode::Gui => Ok(Command::Gui { config, board }),
+        Mode::Headless => {
+            let output = output.unwrap_or_else(|| PathBuf::from(DEFAULT_BOARD_OUTPUT));
+            match resume {
+                Some(resume) => Ok(Command::Resume {
+                    resume,
+                    options,
+                    output,
+                    checkpoint: checkpoint.unwrap(),
+                }),
+                None => Ok(Command::Headless {
+                    config,
+                    output,
+                    checkpoint,
+                }),
+            }
+        }
+        Mode::Export => Ok(Command::Export {
+            board: board.ok_or_else(|| "export needs a board to --open".to_owned())?,
+            output: output.unwrap_or_else(|| PathBuf::from(DEFAULT_IMAGE_OUTPUT)),
+        }),
+        Mode::Dimacs => Ok(Command::Dimacs {
+            config,
+            output: output.unwrap_or_else(|| PathBuf::from(DEFAULT_CNF_OUTPUT)),
+        }),
+    }
+}
+
+fn set_config_option(
+    config: &mut EvolutionConfig,
+    option: &str,
+    value: &str,
+) -> Result<(), String> {
+    match option {
+        "--solver" => config.solver = parse_value(option, value)?,
+        "--board-size" => config.board_size = parse_value(option, value)?,
+        "--population" => config.population_size = parse_value(option, value)?,
+        "--mutation-rate" => {
+            return Err("--mutation-rate was replaced by --mutation teleport=<p>".to_owned())
+        }
+        "--mutation" => {
+            let mut parts = value.splitn(2, '=');
+            let name = parts.next().unwrap();
+            let rate = parts
+                .next()
+                .ok_or_else(|| format!("invalid value for {}: {}", option, value))?;
+            config
+                .mutation_rates
+                .set(name, parse_value(option, rate)?)?;
+        }
+        "--crossover" => config.crossover = parse_value(option, value)?,
+        "--selection" => config.selection = parse_value(option, value)?,
+        "--tournament-size" => config.tournament_size = parse_value(option, value)?,
+        "--elite-count" => config.elite_count = parse_value(option, value)?,
+        "--max-generations" => config.max_generations = Some(parse_value(option, value)?),
+        "--seed" => config.seed = Some(parse_value(option, value)?),
+        "--stagnation-limit" => config.stagnation_limit = Some(parse_value(option, value)?),
+        "--stagnation-boost" => config.stagnation_boost = parse_value(option, value)?,
+        "--immigrant-rate" => config.immigrant_rate = parse_value(option, value)?,
+        "--islands" => config.island_count = parse_value(option, value)?,
+        "--migration-interval" => config.migration_interval = parse_value(option, value)?,
+        "--migrants" => config.migrant_count = parse_value(option, value)?,
+        "--threads" => config.evaluation_threads = Some(parse_value(option, value)?),
+        "--checkpoint-interval" => config.checkpoint_interval = parse_value(option, value)?,
+        "--temperature" => config.initial_temperature = parse_value(option, value)?,
+        "--cooling-rate" => config.cooling_rate = parse_value(option, value)?,
+        _ => return Err(format!("unknown option: {}", option)),
+    }
+    Ok(())
+}
+
+fn find_option<'a>(options: &'a [String], name: &str) -> Result<Option<&'a String>, String> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = next_value(&mut options, option)?;
+        if option == name {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+fn next_value<'a>(
+    options: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a String, String> {
+    options
+        .next()
+        .ok_or_else(|| format!("missing value for {}", option))
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+pub fn usage() -> &'static str {
+    "usage:
+    carcassone [options]                    run the evolution in a GTK window
+    carcassone headless [options]           run the evolution without a display
+    carcassone export --open <file> [--output <image>]
+                                            render a saved board to a .png or .svg image
+    carcassone dimacs [options]             write the perfect board problem as a DIMACS CNF
+
+options:
+    --config <file>             load evolution parameters from a JSON file;
+                                the flags below override its values
+    --solver <kind>             evolution, backtracking (places matching tiles one at a
+                                time; each generation stands for 1000 search steps) or
+                                annealing (mutates a single algorithm; each generation
+                                stands for as many moves as the population size) or sat
+                                (looks for a perfect board within the board size; each
+                                generation stands for 1000 conflicts)
+    --board-size <n>            width and height of the area where tiles are first placed;
+                                boards may grow beyond it, except for the sat solver, which
+                                only gives a complete answer for at least the card count
+    --population <n>            number of algorithms in each generation
+    --mutation <name>=<p>       probability of applying a mutation to an offspring, where
+                                the mutation is one of teleport (to a random position),
+                                rotate, swap (two tiles), shift (to an adjacent free
+                                position), move_cluster or reseat (where all edges match);
+                                can be repeated
+    --crossover <kind>          crossover operator: single-point, two-point, uniform
+                                or rectangle (a region of the board from one parent)
+    --selection <kind>          parent selection: tournament, roulette-wheel, rank-linear
+                                or square-root-rank
+    --tournament-size <n>       algorithms competing in each tournament selection
+    --elite-count <n>           best algorithms copied unchanged to the next generation
+    --max-generations <n>       stop after this many generations
+    --seed <n>                  seed of the random generator, to replay a previous run
+    --stagnation-limit <n>      generations without improvement after which mutation rates
+                                are boosted and random algorithms join the population
+    --stagnation-boost <f>      factor applied to mutation rates at each stagnation, and
+                                undone at each improvement
+    --immigrant-rate <p>        share of the population replaced by random algorithms at
+                                each stagnation
+    --islands <n>               number of populations evolving side by side on separate
+                                threads
+    --migration-interval <n>    generations between two migrations of algorithms to the
+                                next island
+    --migrants <n>              best algorithms sent to the next island at each migration
+    --threads <n>               threads rating each population; all cores are shared
+                                between the islands by default
+    --checkpoint <file>         (headless only) periodically save the whole population
+                                to this file; needs a single island and the evolution
+                                solver
+    --checkpoint-interval <n>   generations between two checkpoints
+    --temperature <f>           initial temperature of the annealing
+    --cooling-rate <f>          factor applied to the annealing temperature after each move
+    --resume <file>             (headless only) continue the evolution saved in a
+                                checkpoint, which keeps writing to it by default;
+                                cannot be combined with --config or --board-size
+    --output <file>             (all but GUI) where to write the final board, the rendered
+                                image or the CNF
+    --open <file>               (GUI and export only) show a saved board instead of
+                                running the evolution"
+}
@@ -0,0 +1,495 @@
+use crate::model::{Board, Card, CardSide, Cell, TerrainType, CLOCKWISE_HALF_SIDES};
+
+const SIDES: [CardSide; 4] = [
+    CardSide::LEFT,
+    CardSide::TOP,
+    CardSide::RIGHT,
+    CardSide::BOTTOM,
+];
+const NO_NODE: usize = usize::MAX;
+
+/// Geometry of a card turned so that a given side faces left. Sides are indexed clockwise from
+/// the left one, half sides clockwise from the bottom half of the left side.
+struct Orientation {
+    sides: [TerrainType; 4],
+    side_structs: [Option<usize>; 4],
+    half_side_fields: [Option<usize>; 8],
+}
+
+struct CardGeometry {
+    monastery: bool,
+    struct_terrains: Vec<TerrainType>,
+    field_towns: Vec<Vec<usize>>,
+    orientations: Vec<Orientation>,
+}
+
+/// Everything evaluation needs to know about a card set, computed once so that boards only
+/// have to store card indices.
+pub struct CardTables {
+    cards: Vec<CardGeometry>,
+    max_structs: usize,
+    opposite_half_sides: [usize; 8],
+}
+
+impl CardTables {
+    pub fn new(cards: &Vec<Card>) -> CardTables {
+        let cards: Vec<CardGeometry> = cards.iter().map(card_geometry).collect();
+        let max_structs = cards
+            .iter()
+            .map(|card| card.struct_terrains.len())
+            .max()
+            .unwrap_or(0);
+        let mut opposite_half_sides = [0; 8];
+        for (index, half_side) in CLOCKWISE_HALF_SIDES.iter().enumerate() {
+            let opposite = half_side.get_opposite();
+            opposite_half_sides[index] = CLOCKWISE_HALF_SIDES
+                .iter()
+                .position(|half_side| half_side == &opposite)
+                .unwrap();
+        }
+        CardTables {
+            cards,
+            max_structs,
+            opposite_half_sides,
+        }
+    }
+}
+
+fn card_geometry(card: &Card) -> CardGeometry {
+    let orientations = (0..4)
+        .map(|rotation| {
+            let mut orientation = Orientation {
+                sides: [
+                    TerrainType::FIELD,
+                    TerrainType::FIELD,
+                    TerrainType::FIELD,
+                    TerrainType::FIELD,
+                ],
+                side_structs: [None; 4],
+                half_side_fields: [None; 8],
+            };
+            for (side, card_side) in SIDES.iter().enumerate() {
+                let geom_side = (side + 4 - rotation) % 4;
+                orientation.sides[geom_side] = card.get_terrain(card_side.clone());
+                orientation.side_structs[geom_side] = card
+                    .structs
+                    .iter()
+                    .position(|struc| struc.sides.contains(card_side));
+            }
+            for (half_side, card_half_side) in CLOCKWISE_HALF_SIDES.iter().enumerate() {
+                let geom_half_side = (half_side + 8 - rotation * 2) % 8;
+                orientation.half_side_fields[geom_half_side] = card
+                    .structs
+                    .iter()
+                    .position(|struc| struc.half_sides.contains(card_half_side));
+            }
+            orientation
+        })
+        .collect();
+    CardGeometry {
+        monastery: card.monastery,
+        struct_terrains: card
+            .structs
+            .iter()
+            .map(|struc| struc.terrain.clone())
+            .collect(),
+        field_towns: card
+            .structs
+            .iter()
+            .map(|struc| struc.towns.clone())
+            .collect(),
+        orientations,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub card: u16,
+    pub rotation: u8,
+}
+
+/// A board stored as a flat array of card indices and rotations, column by column, covering
+/// the smallest rectangle around the tiles.
+pub struct CompactBoard<'a> {
+    tables: &'a CardTables,
+    width: usize,
+    height: usize,
+    tiles: Vec<Option<Tile>>,
+}
+
+impl<'a> CompactBoard<'a> {
+    /// Builds a board from cells listed in the order of the card set, like the cells of an
+    /// algorithm.
+    pub fn from_cells(tables: &'a CardTables, cells: &Vec<Cell>) -> CompactBoard<'a> {
+        let tiles = cells.iter().enumerate().map(|(card, cell)| (card, cell));
+        CompactBoard::from_tiles(tables, tiles)
+    }
+
+    /// Builds a board from any board made of cards of `cards`, the set `tables` was built from.
+    /// Fails if the board holds a card that the set does not have, or has more copies of it.
+    pub fn from_board(
+        tables: &'a CardTables,
+        cards: &Vec<Card>,
+        board: &Board,
+    ) -> Result<CompactBoard<'a>, String> {
+        let mut used_cards = vec![false; cards.len()];
+        let mut tiles: Vec<(usize, &Cell)> = vec![];
+        for cell in board.cells.values() {
+            let card = (0..cards.len())
+                .find(|index| !used_cards[*index] && cards[*index] == cell.card)
+                .ok_or_else(|| format!("card {} is not in the card set", cell.card.pic))?;
+            used_cards[card] = true;
+            tiles.push((card, cell));
+        }
+        Ok(CompactBoard::from_tiles(tables, tiles.into_iter()))
+    }
+
+    fn from_tiles<'c>(
+        tables: &'a CardTables,
+        cells: impl Iterator<Item = (usize, &'c Cell)> + Clone,
+    ) -> CompactBoard<'a> {
+        let (mut left, mut top, mut right, mut bottom) = (0, 0, -1, -1);
+        for (index, (_, cell)) in cells.clone().enumerate() {
+            if index == 0 || cell.pos.x < left {
+                left = cell.pos.x;
+            }
+            if index == 0 || cell.pos.y < top {
+                top = cell.pos.y;
+            }
+            if index == 0 || cell.pos.x > right {
+                right = cell.pos.x;
+            }
+            if index == 0 || cell.pos.y > bottom {
+                bottom = cell.pos.y;
+            }
+        }
+        let width = (right - left + 1) as usize;
+        let height = (bottom - top + 1) as usize;
+        let mut tiles = vec![None; width * height];
+        for (card, cell) in cells {
+            let (x, y) = ((cell.pos.x - left) as usize, (cell.pos.y - top) as usize);
+            tiles[x * height + y] = Some(Tile {
+                card: card as u16,
+                rotation: rotation(&cell.card_side),
+            });
+        }
+        CompactBoard {
+            tables,
+            width,
+            height,
+            tiles,
+        }
+    }
+
+    pub fn count_clusters(&self) -> usize {
+        let mut checked = vec![false; self.tiles.len()];
+        let mut pending = vec![];
+        let mut result = 0;
+        for start in 0..self.tiles.len() {
+            if self.tiles[start].is_none() || checked[start] {
+                continue;
+            }
+            result += 1;
+            checked[start] = true;
+            pending.push(start);
+            while let Some(index) = pending.pop() {
+                for side in 0..4 {
+                    if let Some(neighbour) = self.neighbour(index, side) {
+                        if self.tiles[neighbour].is_some() && !checked[neighbour] {
+                            checked[neighbour] = true;
+                            pending.push(neighbour);
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn count_non_matching_edges(&self) -> usize {
+        let mut result = 0;
+        for index in 0..self.tiles.len() {
+            if let Some(tile) = self.tiles[index] {
+                // Only look right and down so that each edge is counted once.
+                for side in 2..4 {
+                    let neighbour = self.neighbour(index, side).and_then(|n| self.tiles[n]);
+                    if let Some(neighbour) = neighbour {
+                        if self.orientation(tile).sides[side]
+                            != self.orientation(neighbour).sides[opposite(side)]
+                        {
+                            result += 1;
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Counts edges where `terrain` ends at an empty position, at the border of the board or at
+    /// a side of another terrain.
+    pub fn count_unclosed_parts(&self, terrain: &TerrainType) -> usize {
+        let mut result = 0;
+        for index in 0..self.tiles.len() {
+            if let Some(tile) = self.tiles[index] {
+                let orientation = self.orientation(tile);
+                for side in 0..4 {
+                    if &orientation.sides[side] != terrain {
+                        continue;
+                    }
+                    let neighbour = self.neighbour(index, side).and_then(|n| self.tiles[n]);
+                    let closed = neighbour.map_or(false, |neighbour| {
+                        &self.orientation(neighbour).sides[opposite(side)] == terrain
+                    });
+                    if !closed {
+                        result += 1;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn count_unfinished_monasteries(&self) -> usize {
+        let mut result = 0;
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if let Some(tile) = self.tiles[x * self.height + y] {
+                    if self.tables.cards[tile.card as usize].monastery
+                        && self.count_surrounding_tiles(x, y) < 8
+                    {
+                        result += 1;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn count_surrounding_tiles(&self, x: usize, y: usize) -> usize {
+        let mut result = 0;
+        for nx in x.saturating_sub(1)..(x + 2).min(self.width) {
+            for ny in y.saturating_sub(1)..(y + 2).min(self.height) {
+                if (nx, ny) != (x, y) && self.tiles[nx * self.height + ny].is_some() {
+                    result += 1;
+                }
+            }
+        }
+        result
+    }
+
+    pub fn count_structs(&self, terrain: &TerrainType) -> usize {
+        let roots = self.struct_roots(terrain);
+        (0..roots.len())
+            .filter(|node| roots[*node] == *node)
+            .count()
+    }
+
+    pub fn count_towns_not_bordering_largest_field(&self) -> usize {
+        let towns = self.struct_roots(&TerrainType::TOWN);
+        let fields = self.struct_roots(&TerrainType::FIELD);
+        let town_count = (0..towns.len())
+            .filter(|node| towns[*node] == *node)
+            .count();
+        let mut field_sizes = vec![0; fields.len()];
+        for root in fields.iter().filter(|root| **root != NO_NODE) {
+            field_sizes[*root] += 1;
+        }
+        // Fields are ordered by their first tile, and ties go to the last of them.
+        let largest_field = (0..fields.len())
+            .filter(|node| fields[*node] == *node)
+            .fold(None, |largest: Option<usize>, node| match largest {
+                Some(largest) if field_sizes[largest] > field_sizes[node] => Some(largest),
+                _ => Some(node),
+            });
+        let largest_field = match largest_field {
+            Some(field) => field,
+            None => return town_count,
+        };
+        let mut bordering_towns = vec![false; towns.len()];
+        for node in (0..fields.len()).filter(|node| fields[*node] == largest_field) {
+            let (index, struc) = (
+                node / self.tables.max_structs,
+                node % self.tables.max_structs,
+            );
+            let card = &self.tables.cards[self.tiles[index].unwrap().card as usize];
+            for town in &card.field_towns[struc] {
+                bordering_towns[towns[index * self.tables.max_structs + town]] = true;
+            }
+        }
+        town_count
+            - bordering_towns
+                .iter()
+                .filter(|bordering| **bordering)
+                .count()
+    }
+
+    /// Joins the structs of `terrain` that continue across tiles. Nodes stand for a struct of
+    /// a tile, and each node maps to the first node of its struct, or `NO_NODE` when it is not
+    /// a struct of `terrain`.
+    fn struct_roots(&self, terrain: &TerrainType) -> Vec<usize> {
+        let max_structs = self.tables.max_structs;
+        let mut roots = vec![NO_NODE; self.tiles.len() * max_structs];
+        for index in 0..self.tiles.len() {
+            if let Some(tile) = self.tiles[index] {
+                let card = &self.tables.cards[tile.card as usize];
+                for (struc, struct_terrain) in card.struct_terrains.iter().enumerate() {
+                    if struct_terrain == terrain {
+                        roots[index * max_structs + struc] = index * max_structs + struc;
+                    }
+                }
+            }
+        }
+        for index in 0..self.tiles.len() {
+            let tile = match self.tiles[index] {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let orientation = self.orientation(tile);
+            if terrain == &TerrainType::FIELD {
+                for half_side in 0..8 {
+                    let neighbour = self.neighbour(index, half_side / 2);
+                    let neighbour_tile = neighbour.and_then(|n| self.tiles[n]);
+                    if let (Some(field), Some(neighbour), Some(neighbour_tile)) = (
+                        orientation.half_side_fields[half_side],
+                        neighbour,
+                        neighbour_tile,
+                    ) {
+                        let neighbour_orientation = self.orientation(neighbour_tile);
+                        let opposite_half_side = self.tables.opposite_half_sides[half_side];
+                        if let Some(neighbour_field) =
+                            neighbour_orientation.half_side_fields[opposite_half_side]
+                        {
+                            union(
+                                &mut roots,
+                                index * max_structs + field,
+                                neighbour * max_structs + neighbour_field,
+                            );
+                        }
+                    }
+                }
+            } else {
+                for side in 0..4 {
+                    let neighbour = self.neighbour(index, side);
+                    let neighbour_tile = neighbour.and_then(|n| self.tiles[n]);
+                    if let (Some(struc), Some(neighbour), Some(neighbour_tile)) =
+                        (orientation.side_structs[side], neighbour, neighbour_tile)
+                    {
+                        let neighbour_orientation = self.orientation(neighbour_tile);
+                        if &orientation.sides[side] == terrain
+                            && &neighbour_orientation.sides[opposite(side)] == terrain
+                        {
+                            let neighbour_struct =
+                                neighbour_orientation.side_structs[opposite(side)].unwrap();
+                            union(
+                                &mut roots,
+                                index * max_structs + struc,
+                                neighbour * max_structs + neighbour_struct,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        for node in 0..roots.len() {
+            if roots[node] != NO_NODE {
+                roots[node] = find(&mut roots, node);
+            }
+        }
+        roots
+    }
+
+    fn orientation(&self, tile: Tile) -> &Orientation {
+        &self.tables.cards[tile.card as usize].orientations[tile.rotation as usize]
+    }
+
+    fn neighbour(&self, index: usize, side: usize) -> Option<usize> {
+        let (x, y) = (index / self.height, index % self.height);
+        match side {
+            0 if x > 0 => Some(index - self.height),
+            1 if y > 0 => Some(index - 1),
+            2 if x < self.width - 1 => Some(index + self.height),
+            3 if y < self.height - 1 => Some(index + 1),
+            _ => None,
+        }
+    }
+}
+
+fn rotation(card_side: &CardSide) -> u8 {
+    SIDES.iter().position(|side| side == card_side).unwrap() as u8
+}
+
+fn opposite(side: usize) -> usize {
+    (side + 2) % 4
+}
+
+fn find(roots: &mut Vec<usize>, node: usize) -> usize {
+    let mut root = node;
+    while roots[root] != root {
+        root = roots[root];
+    }
+    let mut node = node;
+    while roots[node] != root {
+        let next = roots[node];
+        roots[node] = root;
+        node = next;
+    }
+    root
+}
+
+/// Merges two structs, keeping the smaller node as root so that structs stay ordered by their
+/// first tile.
+fn union(roots: &mut Vec<usize>, node1: usize, node2: usize) {
+    let root1 = find(roots, node1);
+    let root2 = find(roots, node2);
+    if root1 < root2 {
+        roots[root2] = root1;
+    } else if root2 < root1 {
+        roots[root1] = root2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::rearrange_overlaps;
+    use crate::carcassone::{
+        extract_clusters, fill_board, find_non_matching_edges, find_unclosed_parts,
+    };
+    use crate::config::EvolutionConfig;
+    use crate::evolution::{generate_algorithm, seeded_rng};
+    use crate::model::{load_cards, CARDS_PATH};
+    use std::path::Path;
+
+    #[test]
+    fn counts_agree_with_board_helpers() {
+        let cards = load_cards(Path::new(CARDS_PATH)).unwrap();
+        let tables = CardTables::new(&cards);
+        for (seed, board_size) in &[(1, 4), (2, 6), (3, 9), (4, 12), (5, 20)] {
+            let config = EvolutionConfig {
+                board_size: *board_size,
+                seed: Some(*seed),
+                ..EvolutionConfig::default()
+            };
+            let algorithm = generate_algorithm(&config, &mut seeded_rng(&config), &cards);
+            let cells = rearrange_overlaps(&algorithm.cells);
+            let board = fill_board(&cells);
+            for compact in &[
+                CompactBoard::from_cells(&tables, &cells),
+                CompactBoard::from_board(&tables, &cards, &board).unwrap(),
+            ] {
+                assert_eq!(compact.count_clusters(), extract_clusters(&board).len());
+                assert_eq!(
+                    compact.count_non_matching_edges(),
+                    find_non_matching_edges(&board).len()
+                );
+                for terrain in &[TerrainType::TOWN, TerrainType::ROAD] {
+                    assert_eq!(
+                        compact.count_unclosed_parts(terrain),
+                        find_unclosed_parts(&board, terrain).len()
+                    );
+                }
+            }
+        }
+    }
+}
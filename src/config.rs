@@ -0,0 +1,300 @@
+use serde_derive::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvolutionConfig {
+    pub solver: SolverKind,
+    /// Tiles start out in a square of this size, but the board is not limited to it.
+    pub board_size: usize,
+    pub population_size: usize,
+    pub mutation_rates: MutationRates,
+    pub crossover: CrossoverKind,
+    pub selection: SelectionKind,
+    pub tournament_size: usize,
+    pub elite_count: usize,
+    pub max_generations: Option<usize>,
+    pub seed: Option<u64>,
+    pub stagnation_limit: Option<usize>,
+    pub stagnation_boost: f64,
+    pub immigrant_rate: f64,
+    pub island_count: usize,
+    pub migration_interval: usize,
+    pub migrant_count: usize,
+    pub evaluation_threads: Option<usize>,
+    pub checkpoint_interval: usize,
+    pub initial_temperature: f64,
+    pub cooling_rate: f64,
+    pub weights: FitnessWeights,
+    /// The teleport rate in config files written before each mutation had its own rate.
+    #[serde(skip_serializing)]
+    pub mutation_rate: Option<f64>,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        EvolutionConfig {
+            solver: SolverKind::Evolution,
+            board_size: 15,
+            population_size: 50,
+            mutation_rates: MutationRates::default(),
+            crossover: CrossoverKind::SinglePoint,
+            selection: SelectionKind::SquareRootRank,
+            tournament_size: 3,
+            elite_count: 1,
+            max_generations: None,
+            seed: None,
+            stagnation_limit: None,
+            stagnation_boost: 2.0,
+            immigrant_rate: 0.2,
+            island_count: 1,
+            migration_interval: 20,
+            migrant_count: 2,
+            evaluation_threads: None,
+            checkpoint_interval: 50,
+            initial_temperature: 10.0,
+            cooling_rate: 0.9999,
+            weights: FitnessWeights::default(),
+            mutation_rate: None,
+        }
+    }
+}
+
+impl EvolutionConfig {
+    pub fn load(path: &Path) -> Result<EvolutionConfig, String> {
+        let config_file = std::fs::File::open(path)
+            .map_err(|error| format!("cannot open {}: {}", path.display(), error))?;
+        let config: EvolutionConfig = serde_json::from_reader(config_file)
+            .map_err(|error| format!("cannot parse {}: {}", path.display(), error))?;
+        Ok(config.upgraded())
+    }
+
+    /// Moves the values of fields that have been replaced to the fields replacing them.
+    pub fn upgraded(mut self) -> EvolutionConfig {
+        if let Some(rate) = self.mutation_rate.take() {
+            self.mutation_rates.teleport = rate;
+        }
+        self
+    }
+
+    pub fn validate(&self, card_count: usize) -> Result<(), String> {
+        if self.board_size * self.board_size < card_count {
+            return Err(format!(
+                "a {0}x{0} board cannot fit {1} cards",
+                self.board_size, card_count
+            ));
+        }
+        if self.population_size < 2 {
+            return Err("population size must be at least 2".to_owned());
+        }
+        if self.elite_count > self.population_size {
+            return Err("elite count cannot exceed population size".to_owned());
+        }
+        self.mutation_rates.validate()?;
+        if self.tournament_size == 0 {
+            return Err("tournament size must be at least 1".to_owned());
+        }
+        if self.tournament_size > self.population_size {
+            return Err("tournament size cannot exceed population size".to_owned());
+        }
+        if self.stagnation_limit == Some(0) {
+            return Err("stagnation limit must be at least 1".to_owned());
+        }
+        if self.stagnation_boost < 1.0 {
+            return Err("stagnation boost must be at least 1".to_owned());
+        }
+        if self.immigrant_rate < 0.0 || self.immigrant_rate > 1.0 {
+            return Err("immigrant rate must be between 0 and 1".to_owned());
+        }
+        if self.island_count == 0 {
+            return Err("island count must be at least 1".to_owned());
+        }
+        if self.migration_interval == 0 {
+            return Err("migration interval must be at least 1".to_owned());
+        }
+        if self.migrant_count > self.population_size {
+            return Err("migrant count cannot exceed population size".to_owned());
+        }
+        if self.evaluation_threads == Some(0) {
+            return Err("evaluation needs at least 1 thread".to_owned());
+        }
+        if self.checkpoint_interval == 0 {
+            return Err("checkpoint interval must be at least 1".to_owned());
+        }
+        if self.initial_temperature <= 0.0 {
+            return Err("initial temperature must be positive".to_owned());
+        }
+        if self.cooling_rate <= 0.0 || self.cooling_rate > 1.0 {
+            return Err("cooling rate must be above 0 and at most 1".to_owned());
+        }
+        if let SolverKind::Annealing = self.solver {
+            if self.mutation_rates.all_disabled() {
+                return Err("annealing needs at least one mutation".to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FitnessWeights {
+    pub cluster_count: usize,
+    pub unclosed_town_parts: usize,
+    pub non_matching_tiles: usize,
+    pub town_count: usize,
+    pub unclosed_road_parts: usize,
+    pub road_count: usize,
+    pub field_count: usize,
+    pub towns_not_bordering_largest_field: usize,
+    pub unfinished_monasteries: usize,
+}
+
+impl Default for FitnessWeights {
+    fn default() -> Self {
+        FitnessWeights {
+            cluster_count: 1,
+            unclosed_town_parts: 1,
+            non_matching_tiles: 1,
+            town_count: 1,
+            unclosed_road_parts: 0,
+            road_count: 0,
+            field_count: 0,
+            towns_not_bordering_largest_field: 0,
+            unfinished_monasteries: 0,
+        }
+    }
+}
+
+/// Probability of applying each mutation operator to an offspring, independently of the others.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MutationRates {
+    pub teleport: f64,
+    pub rotate: f64,
+    pub swap: f64,
+    pub shift: f64,
+    pub move_cluster: f64,
+    pub reseat: f64,
+}
+
+impl Default for MutationRates {
+    fn default() -> Self {
+        MutationRates {
+            teleport: 0.5,
+            rotate: 0.0,
+            swap: 0.0,
+            shift: 0.0,
+            move_cluster: 0.0,
+            reseat: 0.0,
+        }
+    }
+}
+
+impl MutationRates {
+    pub fn set(&mut self, name: &str, rate: f64) -> Result<(), String> {
+        let field = match name {
+            "teleport" => &mut self.teleport,
+            "rotate" => &mut self.rotate,
+            "swap" => &mut self.swap,
+            "shift" => &mut self.shift,
+            "move_cluster" => &mut self.move_cluster,
+            "reseat" => &mut self.reseat,
+            _ => return Err(format!("unknown mutation: {}", name)),
+        };
+        *field = rate;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.rates().iter().any(|rate| *rate < 0.0 || *rate > 1.0) {
+            return Err("mutation rates must be between 0 and 1".to_owned());
+        }
+        Ok(())
+    }
+
+    fn all_disabled(&self) -> bool {
+        self.rates().iter().all(|rate| *rate == 0.0)
+    }
+
+    fn rates(&self) -> [f64; 6] {
+        [
+            self.teleport,
+            self.rotate,
+            self.swap,
+            self.shift,
+            self.move_cluster,
+            self.reseat,
+        ]
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SolverKind {
+    Evolution,
+    Backtracking,
+    Annealing,
+    Sat,
+}
+
+impl FromStr for SolverKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "evolution" => Ok(SolverKind::Evolution),
+            "backtracking" => Ok(SolverKind::Backtracking),
+            "annealing" => Ok(SolverKind::Annealing),
+            "sat" => Ok(SolverKind::Sat),
+            _ => Err(format!("unknown solver: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CrossoverKind {
+    SinglePoint,
+    TwoPoint,
+    Uniform,
+    Rectangle,
+}
+
+impl FromStr for CrossoverKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "single-point" => Ok(CrossoverKind::SinglePoint),
+            "two-point" => Ok(CrossoverKind::TwoPoint),
+            "uniform" => Ok(CrossoverKind::Uniform),
+            "rectangle" => Ok(CrossoverKind::Rectangle),
+            _ => Err(format!("unknown crossover: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionKind {
+    Tournament,
+    RouletteWheel,
+    RankLinear,
+    SquareRootRank,
+}
+
+impl FromStr for SelectionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tournament" => Ok(SelectionKind::Tournament),
+            "roulette-wheel" => Ok(SelectionKind::RouletteWheel),
+            "rank-linear" => Ok(SelectionKind::RankLinear),
+            "square-root-rank" => Ok(SelectionKind::SquareRootRank),
+            _ => Err(format!("unknown selection: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::algorithm::Algorithm;
+use crate::config::{CrossoverKind, EvolutionConfig};
+use crate::evolution::EvolutionRng;
+use crate::model::Cell;
+use rand::Rng;
+
+/// Combines two parents into the cells of an offspring. Cells at the same index hold the same
+/// card in every algorithm, so operators pick each cell from either parent without losing or
+/// duplicating cards.
+pub trait Crossover {
+    fn crossover(
+        &self,
+        rng: &mut EvolutionRng,
+        algorithm1: &Algorithm,
+        algorithm2: &Algorithm,
+    ) -> Vec<Cell>;
+}
+
+struct SinglePoint;
+
+impl Crossover for SinglePoint {
+    fn crossover(
+        &self,
+        rng: &mut EvolutionRng,
+        algorithm1: &Algorithm,
+        algorithm2: &Algorithm,
+    ) -> Vec<Cell> {
+        let index = rng.gen_range(0, algorithm1.cells.len());
+        let (first_part, _) = algorithm1.cells.split_at(index);
+        let (_, second_part) = algorithm2.cells.split_at(index);
+        let mut cells: Vec<Cell> = vec![];
+        cells.extend_from_slice(first_part);
+        cells.extend_from_slice(second_part);
+        cells
+    }
+}
+
+struct TwoPoint;
+
+impl Crossover for TwoPoint {
+    fn crossover(
+        &self,
+        rng: &mut EvolutionRng,
+        algorithm1: &Algorithm,
+        algorithm2: &Algorithm,
+    ) -> Vec<Cell> {
+        let index1 = rng.gen_range(0, algorithm1.cells.len());
+        let index2 = rng.gen_range(0, algorithm1.cells.len());
+        let (start, end) = (index1.min(index2), index1.max(index2));
+        (0..algorithm1.cells.len())
+            .map(|index| {
+                if index >= start && index < end {
+                    algorithm2.cells[index].clone()
+                } else {
+                    algorithm1.cells[index].clone()
+                }
+            })
+            .collect()
+    }
+}
+
+struct Uniform;
+
+impl Crossover for Uniform {
+    fn crossover(
+        &self,
+        rng: &mut EvolutionRng,
+        algorithm1: &Algorithm,
+        algorithm2: &Algorithm,
+    ) -> Vec<Cell> {
+        algorithm1
+            .cells
+            .iter()
+            .zip(&algorithm2.cells)
+            .map(|(cell1, cell2)| {
+                if rng.gen() {
+                    cell1.clone()
+                } else {
+                    cell2.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Keeps the tiles the first parent placed inside a random region of the board and takes the
+/// placement of every other card from the second parent. Cards of the second parent that land
+/// inside the region are moved aside when the offspring rearranges its overlaps.
+struct Rectangle {
+    board_size: i32,
+}
+
+impl Crossover for Rectangle {
+    fn crossover(
+        &self,
+        rng: &mut EvolutionRng,
+        algorithm1: &Algorithm,
+        algorithm2: &Algorithm,
+    ) -> Vec<Cell> {
+        let x1 = rng.gen_range(0, self.board_size);
+        let x2 = rng.gen_range(0, self.board_size);
+        let y1 = rng.gen_range(0, self.board_size);
+        let y2 = rng.gen_range(0, self.board_size);
+        let (left, right) = (x1.min(x2), x1.max(x2));
+        let (top, bottom) = (y1.min(y2), y1.max(y2));
+        algorithm1
+            .arranged_cells
+            .iter()
+            .zip(&algorithm2.arranged_cells)
+            .map(|(cell1, cell2)| {
+                let pos = &cell1.pos;
+                if pos.x >= left && pos.x <= right && pos.y >= top && pos.y <= bottom {
+                    cell1.clone()
+                } else {
+                    cell2.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn crossover(config: &EvolutionConfig) -> Box<dyn Crossover> {
+    match config.crossover {
+        CrossoverKind::SinglePoint => Box::new(SinglePoint),
+        CrossoverKind::TwoPoint => Box::new(TwoPoint),
+        CrossoverKind::Uniform => Box::new(Uniform),
+        CrossoverKind::Rectangle => Box::new(Rectangle {
+            board_size: config.board_size as i32,
+        }),
+    }
+}
@@ -1,61 +1,326 @@
 use crate::algorithm::Algorithm;
-use crate::carcassone::{evaluate_algorithm, fill_board};
-use crate::model::{Board, Card, CardSide, Cell, Pos};
-use glib::Sender;
-use rand::prelude::ThreadRng;
-use rand::Rng;
-
-const FIELD_SIZE: usize = 15;
-const POPULATION_SIZE: usize = 50;
-const MUTATION_CHANCE: f64 = 0.5;
-
-pub fn start_evolution(cards: &Vec<Card>, sender: &Sender<RatedBoard>) {
-    let mut population: Vec<Algorithm> = (0..POPULATION_SIZE)
-        .map(|_| generate_algorithm(cards))
+use crate::carcassone::fill_board;
+use crate::checkpoint::save_checkpoint;
+use crate::config::EvolutionConfig;
+use crate::crossover::{crossover, Crossover};
+use crate::fitness::{Evaluator, Fitness};
+use crate::model::{Card, CardSide, Cell, Pos};
+use crate::mutation::{mutations, WeightedMutation};
+use crate::selection::{selection, Selection};
+use crate::solver::{RatedBoard, Solver};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use serde_derive::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+pub type EvolutionRng = Pcg64;
+
+/// Everything needed to continue an evolution: the population about to be rated, the number
+/// of its generation and the random generator that will breed the next one.
+pub struct EvolutionState {
+    pub generation: usize,
+    pub rng: EvolutionRng,
+    pub population: Vec<Algorithm>,
+    pub hall_of_fame: Option<HallOfFame>,
+    pub stagnation: Stagnation,
+}
+
+/// The best algorithm seen since the start of the evolution, which may have been lost from the
+/// population since.
+#[derive(Clone)]
+pub struct HallOfFame {
+    pub fitness: Fitness,
+    pub algorithm: Algorithm,
+}
+
+/// Tracks how long the best score has not improved. Mutation rates are multiplied by `boost`,
+/// which grows while the evolution is stuck and decays back to 1 once it improves again.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stagnation {
+    pub generations: usize,
+    pub boost: f64,
+}
+
+impl Default for Stagnation {
+    fn default() -> Self {
+        Stagnation {
+            generations: 0,
+            boost: 1.0,
+        }
+    }
+}
+
+struct Operators {
+    selection: Box<dyn Selection>,
+    crossover: Box<dyn Crossover>,
+    mutations: Vec<WeightedMutation>,
+}
+
+/// The random generator of a new run, seeded from the config when a seed is given. The seed
+/// is printed so that the run can be replayed.
+pub fn seeded_rng(config: &EvolutionConfig) -> EvolutionRng {
+    let seed = config.seed.unwrap_or_else(rand::random);
+    println!("seed: {}", seed);
+    EvolutionRng::seed_from_u64(seed)
+}
+
+pub fn initial_state(
+    config: &EvolutionConfig,
+    cards: &Vec<Card>,
+    mut rng: EvolutionRng,
+) -> EvolutionState {
+    let population: Vec<Algorithm> = (0..config.population_size)
+        .map(|_| generate_algorithm(config, &mut rng, cards))
         .collect();
-    let mut result_found = false;
-    while !result_found {
-        let mut rated_algs: Vec<(usize, Algorithm)> = population
-            .into_iter()
-            .map(|algorithm| (evaluate_algorithm(&algorithm), algorithm))
-            .collect();
-        rated_algs.sort_by_key(|(score, _)| *score);
-        let (best_result, best_alg) = rated_algs[0].clone();
-        let rated_algs: Vec<Algorithm> = rated_algs.into_iter().map(|(_, alg)| alg).collect();
-        population = next_generation(cards, &rated_algs);
-        let board = fill_board(&best_alg.arranged_cells);
-        sender.send(RatedBoard {
-            score: best_result,
-            board,
+    EvolutionState {
+        generation: 0,
+        rng,
+        population,
+        hall_of_fame: None,
+        stagnation: Stagnation::default(),
+    }
+}
+
+/// A population that evolves one generation at a time.
+pub struct Evolution<'a> {
+    config: &'a EvolutionConfig,
+    cards: &'a Vec<Card>,
+    operators: Operators,
+    evaluation_pool: EvaluationPool,
+    pub state: EvolutionState,
+    ranked: Vec<Algorithm>,
+}
+
+impl<'a> Evolution<'a> {
+    pub fn new(
+        config: &'a EvolutionConfig,
+        cards: &'a Vec<Card>,
+        state: EvolutionState,
+    ) -> Evolution<'a> {
+        let operators = Operators {
+            selection: selection(config),
+            crossover: crossover(config),
+            mutations: mutations(&config.mutation_rates),
+        };
+        let threads = config.evaluation_threads.unwrap_or_else(|| {
+            let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+            (cores / config.island_count).max(1)
         });
-        if best_result == 0 {
-            result_found = true;
+        Evolution {
+            config,
+            cards,
+            operators,
+            evaluation_pool: EvaluationPool::new(Evaluator::new(&config.weights, cards), threads),
+            state,
+            ranked: vec![],
         }
     }
+
+    /// The best algorithms of the last rated generation.
+    pub fn best_algorithms(&self, count: usize) -> Vec<Algorithm> {
+        self.ranked.iter().take(count).cloned().collect()
+    }
+
+    /// Replaces offspring at the end of the population, never the elites, and returns how many
+    /// were replaced.
+    pub fn replace_offspring(&mut self, mut algorithms: Vec<Algorithm>) -> usize {
+        let population = &mut self.state.population;
+        let replaceable = population.len().saturating_sub(self.config.elite_count);
+        algorithms.truncate(replaceable);
+        population.truncate(population.len() - algorithms.len());
+        let count = algorithms.len();
+        population.extend(algorithms);
+        count
+    }
 }
 
-pub fn create_empty_board() -> Board {
-    Board {
-        width: FIELD_SIZE,
-        height: FIELD_SIZE,
-        cells: vec![vec![None; FIELD_SIZE]; FIELD_SIZE],
+impl Solver for Evolution<'_> {
+    /// Rates the current population, breeds the next one and returns the best board found
+    /// so far.
+    fn step(&mut self) -> RatedBoard {
+        let (config, cards) = (self.config, self.cards);
+        let state = &mut self.state;
+        let mut rated_algs = self
+            .evaluation_pool
+            .evaluate(std::mem::take(&mut state.population));
+        rated_algs.sort_by_key(|(fitness, _)| fitness.score);
+        let improved = state
+            .hall_of_fame
+            .as_ref()
+            .map_or(true, |best| rated_algs[0].0.score < best.fitness.score);
+        if improved {
+            let (fitness, algorithm) = rated_algs[0].clone();
+            state.hall_of_fame = Some(HallOfFame { fitness, algorithm });
+        }
+        let scores: Vec<usize> = rated_algs
+            .iter()
+            .map(|(fitness, _)| fitness.score)
+            .collect();
+        self.ranked = rated_algs.into_iter().map(|(_, alg)| alg).collect();
+        state.population = next_generation(
+            config,
+            &mut state.rng,
+            &self.operators,
+            state.stagnation.boost,
+            &scores,
+            &self.ranked,
+        );
+        if let Some(stagnation_limit) = config.stagnation_limit {
+            if improved {
+                state.stagnation.generations = 0;
+                state.stagnation.boost =
+                    (state.stagnation.boost / config.stagnation_boost).max(1.0);
+            } else {
+                state.stagnation.generations += 1;
+            }
+            if state.stagnation.generations >= stagnation_limit {
+                state.stagnation.generations = 0;
+                state.stagnation.boost *= config.stagnation_boost;
+                let count = (config.population_size as f64 * config.immigrant_rate) as usize;
+                let immigrants = (0..count)
+                    .map(|_| generate_algorithm(config, &mut state.rng, cards))
+                    .collect();
+                let immigrant_count = self.replace_offspring(immigrants);
+                println!(
+                    "no improvement for {} generations, mutation boost {}, {} new algorithms",
+                    stagnation_limit, self.state.stagnation.boost, immigrant_count
+                );
+            }
+        }
+        self.state.generation += 1;
+        self.best().unwrap()
+    }
+
+    fn best(&self) -> Option<RatedBoard> {
+        self.state.hall_of_fame.as_ref().map(|best| RatedBoard {
+            generation: self.state.generation.saturating_sub(1),
+            fitness: best.fitness.clone(),
+            board: fill_board(&best.algorithm.arranged_cells),
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        let out_of_generations = self
+            .config
+            .max_generations
+            .map_or(false, |max_generations| {
+                self.state.generation >= max_generations
+            });
+        let perfect = self
+            .state
+            .hall_of_fame
+            .as_ref()
+            .map_or(false, |best| best.fitness.score == 0);
+        perfect || out_of_generations
+    }
+
+    fn save_checkpoint(&self, path: &Path, cards_path: &Path) -> Result<(), String> {
+        save_checkpoint(path, self.config, &self.state, cards_path)
+    }
+}
+
+type RatedAlgorithms = Vec<(Fitness, Algorithm)>;
+
+/// Rates populations on threads that live as long as the evolution, so that a generation does
+/// not pay for starting them. The results keep the order of the population, so they do not
+/// depend on the number of threads.
+struct EvaluationPool {
+    evaluator: Arc<Evaluator>,
+    jobs: Vec<Sender<(usize, Vec<Algorithm>)>>,
+    results: Receiver<(usize, RatedAlgorithms)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl EvaluationPool {
+    /// Starts no thread at all for a single thread, as rating on the caller is then faster.
+    fn new(evaluator: Evaluator, threads: usize) -> EvaluationPool {
+        let evaluator = Arc::new(evaluator);
+        let (result_sender, results) = channel();
+        let mut jobs = vec![];
+        let mut workers = vec![];
+        let worker_count = if threads > 1 { threads } else { 0 };
+        for _ in 0..worker_count {
+            let (job_sender, job_receiver) = channel::<(usize, Vec<Algorithm>)>();
+            let evaluator = evaluator.clone();
+            let result_sender = result_sender.clone();
+            workers.push(std::thread::spawn(move || {
+                for (index, chunk) in job_receiver {
+                    let rated = rate(&evaluator, chunk);
+                    if result_sender.send((index, rated)).is_err() {
+                        return;
+                    }
+                }
+            }));
+            jobs.push(job_sender);
+        }
+        EvaluationPool {
+            evaluator,
+            jobs,
+            results,
+            workers,
+        }
+    }
+
+    fn evaluate(&self, population: Vec<Algorithm>) -> RatedAlgorithms {
+        if self.jobs.is_empty() {
+            return rate(&self.evaluator, population);
+        }
+        let chunk_size = (population.len() + self.jobs.len() - 1) / self.jobs.len();
+        let mut population = population.into_iter();
+        let mut chunk_count = 0;
+        for jobs in &self.jobs {
+            let chunk: Vec<Algorithm> = population.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            jobs.send((chunk_count, chunk)).unwrap();
+            chunk_count += 1;
+        }
+        let mut chunks = vec![vec![]; chunk_count];
+        for _ in 0..chunk_count {
+            let (index, rated) = self.results.recv().unwrap();
+            chunks[index] = rated;
+        }
+        chunks.into_iter().flatten().collect()
     }
 }
 
-fn generate_algorithm(cards: &Vec<Card>) -> Algorithm {
-    let mut rng = rand::thread_rng();
+impl Drop for EvaluationPool {
+    /// Closing the job channels ends the workers.
+    fn drop(&mut self) {
+        self.jobs.clear();
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+fn rate(evaluator: &Evaluator, algorithms: Vec<Algorithm>) -> RatedAlgorithms {
+    algorithms
+        .into_iter()
+        .map(|algorithm| {
+            let fitness = evaluator.evaluate_cells(&algorithm.arranged_cells);
+            (fitness, algorithm)
+        })
+        .collect()
+}
+
+pub fn generate_algorithm(
+    config: &EvolutionConfig,
+    rng: &mut EvolutionRng,
+    cards: &Vec<Card>,
+) -> Algorithm {
     let cells = (0..cards.len())
         .map(|card_id| {
             let pos = Pos {
-                x: rng.gen_range(0, FIELD_SIZE),
-                y: rng.gen_range(0, FIELD_SIZE),
-            };
-            let card_side = match rng.gen_range(0, 4) {
-                0 => CardSide::LEFT,
-                1 => CardSide::TOP,
-                2 => CardSide::RIGHT,
-                _ => CardSide::BOTTOM,
+                x: rng.gen_range(0, config.board_size as i32),
+                y: rng.gen_range(0, config.board_size as i32),
             };
+            let card_side = random_card_side(rng);
             Cell {
                 pos,
                 card: cards[card_id].clone(),
@@ -66,61 +331,84 @@ fn generate_algorithm(cards: &Vec<Card>) -> Algorithm {
     Algorithm::new(cells)
 }
 
-fn next_generation(cards: &Vec<Card>, rated_algorithms: &Vec<Algorithm>) -> Vec<Algorithm> {
-    let mut result = vec![];
-    let mut rng = rand::thread_rng();
-    for _ in 0..POPULATION_SIZE {
-        let index1 = select_index(&mut rng);
-        let mut index2 = select_index(&mut rng);
+fn next_generation(
+    config: &EvolutionConfig,
+    rng: &mut EvolutionRng,
+    operators: &Operators,
+    mutation_boost: f64,
+    scores: &Vec<usize>,
+    rated_algorithms: &Vec<Algorithm>,
+) -> Vec<Algorithm> {
+    let mut result: Vec<Algorithm> = rated_algorithms
+        .iter()
+        .take(config.elite_count)
+        .cloned()
+        .collect();
+    while result.len() < config.population_size {
+        let index1 = operators.selection.select(rng, scores);
+        let mut index2 = operators.selection.select(rng, scores);
         while index2 == index1 {
-            index2 = select_index(&mut rng);
+            index2 = operators.selection.select(rng, scores);
         }
         let parent1 = &rated_algorithms[index1];
         let parent2 = &rated_algorithms[index2];
-        result.push(breed(cards, parent1, parent2));
+        result.push(breed(
+            config,
+            rng,
+            operators,
+            mutation_boost,
+            parent1,
+            parent2,
+        ));
     }
     result
 }
 
-fn select_index(rng: &mut ThreadRng) -> usize {
-    let rand: f64 = rng.gen_range(0.0, 1.0);
-    ((1.0 - ((1.0 - rand).sqrt())) * POPULATION_SIZE as f64) as usize
-}
-
-fn breed(cards: &Vec<Card>, algorithm1: &Algorithm, algorithm2: &Algorithm) -> Algorithm {
-    let mut rng = rand::thread_rng();
-    let index = rng.gen_range(0, cards.len());
-    let (first_part, _) = algorithm1.cells.split_at(index);
-    let (_, second_part) = algorithm2.cells.split_at(index);
-    let mut cells: Vec<Cell> = vec![];
-    cells.extend_from_slice(first_part);
-    cells.extend_from_slice(second_part);
-    mutate(&mut rng, &mut cells);
+fn breed(
+    config: &EvolutionConfig,
+    rng: &mut EvolutionRng,
+    operators: &Operators,
+    mutation_boost: f64,
+    algorithm1: &Algorithm,
+    algorithm2: &Algorithm,
+) -> Algorithm {
+    let mut cells = operators.crossover.crossover(rng, algorithm1, algorithm2);
+    for mutation in &operators.mutations {
+        if rng.gen_range(0.0, 1.0) < mutation.probability * mutation_boost {
+            mutation.mutation.mutate(config, rng, &mut cells);
+        }
+    }
     Algorithm::new(cells)
 }
 
-fn mutate(rng: &mut ThreadRng, cells: &mut Vec<Cell>) {
-    if rng.gen_range(0.0, 1.0) > MUTATION_CHANCE {
-        let mutation_index = rng.gen_range(0, cells.len());
-        let mutating_cell = cells[mutation_index].clone();
-        let card_side = match rng.gen_range(0, 4) {
-            0 => CardSide::LEFT,
-            1 => CardSide::TOP,
-            2 => CardSide::RIGHT,
-            _ => CardSide::BOTTOM,
-        };
-        cells[mutation_index] = Cell {
-            pos: Pos {
-                x: rng.gen_range(0, FIELD_SIZE),
-                y: rng.gen_range(0, FIELD_SIZE),
-            },
-            card: mutating_cell.card,
-            card_side,
-        };
+pub fn random_card_side(rng: &mut EvolutionRng) -> CardSide {
+    match rng.gen_range(0, 4) {
+        0 => CardSide::LEFT,
+        1 => CardSide::TOP,
+        2 => CardSide::RIGHT,
+        _ => CardSide::BOTTOM,
     }
 }
 
-pub struct RatedBoard {
-    pub score: usize,
-    pub board: Board,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SelectionKind;
+    use crate::model::{load_cards, CARDS_PATH};
+
+    #[test]
+    fn tournament_as_large_as_population_finishes_a_generation() {
+        let cards = load_cards(Path::new(CARDS_PATH)).unwrap();
+        let config = EvolutionConfig {
+            selection: SelectionKind::Tournament,
+            population_size: 4,
+            tournament_size: 4,
+            seed: Some(1),
+            ..EvolutionConfig::default()
+        };
+        assert!(config.validate(cards.len()).is_ok());
+        let state = initial_state(&config, &cards, seeded_rng(&config));
+        let mut evolution = Evolution::new(&config, &cards, state);
+        assert_eq!(evolution.step().generation, 0);
+    }
 }
@@ -0,0 +1,212 @@
+use crate::compact::{CardTables, CompactBoard};
+use crate::config::FitnessWeights;
+use crate::model::{Board, Card, Cell, TerrainType};
+
+pub trait FitnessTerm: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn evaluate(&self, board: &CompactBoard) -> usize;
+}
+
+struct ClusterCount;
+
+impl FitnessTerm for ClusterCount {
+    fn name(&self) -> &'static str {
+        "cluster_count"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_clusters().saturating_sub(1)
+    }
+}
+
+struct UnclosedTownParts;
+
+impl FitnessTerm for UnclosedTownParts {
+    fn name(&self) -> &'static str {
+        "unclosed_town_parts"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_unclosed_parts(&TerrainType::TOWN)
+    }
+}
+
+struct NonMatchingTiles;
+
+impl FitnessTerm for NonMatchingTiles {
+    fn name(&self) -> &'static str {
+        "non_matching_tiles"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_non_matching_edges()
+    }
+}
+
+struct TownCount;
+
+impl FitnessTerm for TownCount {
+    fn name(&self) -> &'static str {
+        "town_count"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_structs(&TerrainType::TOWN)
+    }
+}
+
+struct UnclosedRoadParts;
+
+impl FitnessTerm for UnclosedRoadParts {
+    fn name(&self) -> &'static str {
+        "unclosed_road_parts"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_unclosed_parts(&TerrainType::ROAD)
+    }
+}
+
+struct RoadCount;
+
+impl FitnessTerm for RoadCount {
+    fn name(&self) -> &'static str {
+        "road_count"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_structs(&TerrainType::ROAD)
+    }
+}
+
+struct FieldCount;
+
+impl FitnessTerm for FieldCount {
+    fn name(&self) -> &'static str {
+        "field_count"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_structs(&TerrainType::FIELD)
+    }
+}
+
+struct TownsNotBorderingLargestField;
+
+impl FitnessTerm for TownsNotBorderingLargestField {
+    fn name(&self) -> &'static str {
+        "towns_not_bordering_largest_field"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_towns_not_bordering_largest_field()
+    }
+}
+
+struct UnfinishedMonasteries;
+
+impl FitnessTerm for UnfinishedMonasteries {
+    fn name(&self) -> &'static str {
+        "unfinished_monasteries"
+    }
+
+    fn evaluate(&self, board: &CompactBoard) -> usize {
+        board.count_unfinished_monasteries()
+    }
+}
+
+pub struct WeightedTerm {
+    pub term: Box<dyn FitnessTerm>,
+    pub weight: usize,
+}
+
+pub fn fitness_terms(weights: &FitnessWeights) -> Vec<WeightedTerm> {
+    let terms: Vec<(usize, Box<dyn FitnessTerm>)> = vec![
+        (weights.cluster_count, Box::new(ClusterCount)),
+        (weights.unclosed_town_parts, Box::new(UnclosedTownParts)),
+        (weights.non_matching_tiles, Box::new(NonMatchingTiles)),
+        (weights.town_count, Box::new(TownCount)),
+        (weights.unclosed_road_parts, Box::new(UnclosedRoadParts)),
+        (weights.road_count, Box::new(RoadCount)),
+        (weights.field_count, Box::new(FieldCount)),
+        (
+            weights.towns_not_bordering_largest_field,
+            Box::new(TownsNotBorderingLargestField),
+        ),
+        (
+            weights.unfinished_monasteries,
+            Box::new(UnfinishedMonasteries),
+        ),
+    ];
+    terms
+        .into_iter()
+        .filter(|(weight, _)| *weight > 0)
+        .map(|(weight, term)| WeightedTerm { term, weight })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct FitnessComponent {
+    pub name: &'static str,
+    pub value: usize,
+    pub weight: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Fitness {
+    pub score: usize,
+    pub breakdown: Vec<FitnessComponent>,
+}
+
+impl std::fmt::Display for Fitness {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let components = self
+            .breakdown
+            .iter()
+            .map(|component| format!("{}: {}", component.name, component.value))
+            .collect::<Vec<_>>();
+        write!(f, "{} ({})", self.score, components.join(", "))
+    }
+}
+
+/// Rates boards made of the cards of one card set.
+pub struct Evaluator {
+    terms: Vec<WeightedTerm>,
+    tables: CardTables,
+}
+
+impl Evaluator {
+    pub fn new(weights: &FitnessWeights, cards: &Vec<Card>) -> Evaluator {
+        Evaluator {
+            terms: fitness_terms(weights),
+            tables: CardTables::new(cards),
+        }
+    }
+
+    /// Rates cells listed in the order of the card set, like the cells of an algorithm.
+    pub fn evaluate_cells(&self, cells: &Vec<Cell>) -> Fitness {
+        let board = CompactBoard::from_cells(&self.tables, cells);
+        evaluate_board(&self.terms, &board)
+    }
+
+    pub fn evaluate_board(&self, cards: &Vec<Card>, board: &Board) -> Result<Fitness, String> {
+        let board = CompactBoard::from_board(&self.tables, cards, board)?;
+        Ok(evaluate_board(&self.terms, &board))
+    }
+}
+
+fn evaluate_board(terms: &Vec<WeightedTerm>, board: &CompactBoard) -> Fitness {
+    let breakdown: Vec<FitnessComponent> = terms
+        .iter()
+        .map(|weighted_term| FitnessComponent {
+            name: weighted_term.term.name(),
+            value: weighted_term.term.evaluate(board),
+            weight: weighted_term.weight,
+        })
+        .collect();
+    let score = breakdown
+        .iter()
+        .map(|component| component.value * component.weight)
+        .sum();
+    Fitness { score, breakdown }
+}
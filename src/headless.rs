@@ -0,0 +1,67 @@
+use crate::board_file::save_board;
+use crate::config::{EvolutionConfig, SolverKind};
+use crate::evolution::EvolutionState;
+use crate::model::Card;
+use crate::solver::{run_solver, solver, ProgressSink, RatedBoard, Solver};
+use std::path::Path;
+
+struct ConsoleProgress<'a> {
+    config: &'a EvolutionConfig,
+    cards_path: &'a Path,
+    checkpoint: Option<&'a Path>,
+}
+
+impl ProgressSink for ConsoleProgress<'_> {
+    fn report(&self, result: RatedBoard) {
+        println!(
+            "generation {}: best result {}",
+            result.generation, result.fitness
+        );
+    }
+
+    fn checkpoint(&self, generation: usize, solver: &dyn Solver) {
+        if let Some(path) = self.checkpoint {
+            // Generations are counted from 0, so this saves after every full interval.
+            if (generation + 1) % self.config.checkpoint_interval == 0 {
+                match solver.save_checkpoint(path, self.cards_path) {
+                    Ok(()) => println!("checkpoint written to {}", path.display()),
+                    Err(error) => eprintln!("{}", error),
+                }
+            }
+        }
+    }
+
+    fn status(&self, status: &str) {
+        println!("{}", status);
+    }
+}
+
+pub fn run_headless(
+    config: &EvolutionConfig,
+    cards_path: &Path,
+    cards: &Vec<Card>,
+    output: &Path,
+    checkpoint: Option<&Path>,
+    resume: Option<EvolutionState>,
+) -> Result<(), String> {
+    if checkpoint.is_some() && config.island_count > 1 {
+        return Err("checkpoints need a single island".to_owned());
+    }
+    if checkpoint.is_some() && !matches!(config.solver, SolverKind::Evolution) {
+        return Err("checkpoints need the evolution solver".to_owned());
+    }
+    let progress = ConsoleProgress {
+        config,
+        cards_path,
+        checkpoint,
+    };
+    let result = run_solver(solver(config, cards, resume).as_mut(), &progress);
+    save_board(output, &result.board, cards_path, cards)?;
+    println!(
+        "finished in generation {} with result {}, board written to {}",
+        result.generation,
+        result.fitness,
+        output.display()
+    );
+    Ok(())
+}
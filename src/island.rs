@@ -0,0 +1,119 @@
+use crate::algorithm::Algorithm;
+use crate::config::EvolutionConfig;
+use crate::evolution::{initial_state, Evolution, EvolutionRng};
+use crate::model::Card;
+use crate::solver::{RatedBoard, Solver};
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Barrier};
+use std::thread::JoinHandle;
+
+/// Evolves `island_count` independent populations, each on its own thread. Every
+/// `migration_interval` generations an island sends copies of its best algorithms to the next
+/// island of a ring and waits for those of the previous one. The islands only decide to stop
+/// at these points, all at the same generation, and equal scores go to the island that comes
+/// first, so a seeded run stays reproducible. Each step waits for the best board of all
+/// islands to improve or for the slowest island to finish another generation.
+pub struct Islands {
+    result_rx: Receiver<(usize, RatedBoard)>,
+    islands: Vec<JoinHandle<()>>,
+    best: Option<(usize, RatedBoard)>,
+    generation: usize,
+}
+
+impl Islands {
+    pub fn new(config: &EvolutionConfig, cards: &Vec<Card>, rng: &mut EvolutionRng) -> Islands {
+        let stop = Arc::new(AtomicBool::new(false));
+        let barrier = Arc::new(Barrier::new(config.island_count));
+        let (result_tx, result_rx) = channel();
+        let (migrant_txs, migrant_rxs): (
+            Vec<Sender<Vec<Algorithm>>>,
+            Vec<Receiver<Vec<Algorithm>>>,
+        ) = (0..config.island_count).map(|_| channel()).unzip();
+        let mut islands = vec![];
+        for (island, migrant_rx) in migrant_rxs.into_iter().enumerate() {
+            let config = config.clone();
+            let cards = cards.clone();
+            let rng = EvolutionRng::seed_from_u64(rng.gen());
+            let migrant_tx = migrant_txs[(island + 1) % config.island_count].clone();
+            let result_tx = result_tx.clone();
+            let stop = stop.clone();
+            let barrier = barrier.clone();
+            islands.push(std::thread::spawn(move || {
+                let state = initial_state(&config, &cards, rng);
+                let mut evolution = Evolution::new(&config, &cards, state);
+                loop {
+                    let _ = result_tx.send((island, evolution.step()));
+                    // An island that found a perfect board keeps evolving until the others
+                    // learn about it at the next migration.
+                    if evolution.is_done() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    let generation = evolution.state.generation;
+                    let migrating = generation % config.migration_interval == 0;
+                    if !migrating && config.max_generations != Some(generation) {
+                        continue;
+                    }
+                    barrier.wait();
+                    let stopping = stop.load(Ordering::Relaxed);
+                    // Nobody may set the flag again before every island has read it.
+                    barrier.wait();
+                    if stopping {
+                        return;
+                    }
+                    if migrating {
+                        let _ = migrant_tx.send(evolution.best_algorithms(config.migrant_count));
+                        // The previous island only hangs up once it has stopped.
+                        match migrant_rx.recv() {
+                            Ok(migrants) => evolution.replace_offspring(migrants),
+                            Err(_) => return,
+                        };
+                    }
+                }
+            }));
+        }
+        drop(result_tx);
+        drop(migrant_txs);
+        Islands {
+            result_rx,
+            islands,
+            best: None,
+            generation: 0,
+        }
+    }
+}
+
+impl Solver for Islands {
+    fn step(&mut self) -> RatedBoard {
+        for (island, result) in self.result_rx.iter() {
+            let improved = self.best.as_ref().map_or(true, |(best_island, best)| {
+                (result.fitness.score, island) < (best.fitness.score, *best_island)
+            });
+            if improved || result.generation > self.generation {
+                self.generation = self.generation.max(result.generation);
+                if improved {
+                    self.best = Some((island, result));
+                }
+                return self.best().unwrap();
+            }
+        }
+        // Every island has stopped.
+        for island in self.islands.drain(..) {
+            island.join().unwrap();
+        }
+        self.best().unwrap()
+    }
+
+    fn best(&self) -> Option<RatedBoard> {
+        self.best.as_ref().map(|(_, best)| RatedBoard {
+            generation: self.generation,
+            fitness: best.fitness.clone(),
+            board: best.board.clone(),
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        self.islands.is_empty()
+    }
+}
@@ -1,62 +1,206 @@
 mod algorithm;
+mod annealing;
+mod backtracking;
+mod board_file;
 mod carcassone;
+mod checkpoint;
+mod cli;
+mod compact;
+mod config;
+mod crossover;
 mod evolution;
+mod fitness;
+mod headless;
+mod island;
 mod model;
+mod mutation;
+mod perfect_board;
+mod render;
+mod sat;
+mod selection;
+mod solver;
 
-use crate::evolution::{create_empty_board, start_evolution};
+use crate::board_file::{load_board, save_board};
+use crate::checkpoint::load_checkpoint;
+use crate::cli::{parse_args, usage, Command};
+use crate::config::EvolutionConfig;
+use crate::fitness::{fitness_terms, Evaluator};
+use crate::headless::run_headless;
 use crate::model::{
-    bottom_side, left_side, right_side, top_side, Board, Card, CardSide, TerrainType,
+    bottom_side, left_side, load_cards, right_side, top_side, Board, Card, TerrainType, CARDS_PATH,
 };
+use crate::perfect_board::PerfectBoard;
+use crate::render::{BoardRenderer, TILE_SIZE};
+use crate::solver::{solve, ProgressSink, RatedBoard};
 use cairo::ImageSurface;
-use gdk::prelude::GdkContextExt;
-use gdk_pixbuf::Pixbuf;
 use gio::prelude::*;
 use gio::ApplicationFlags;
 use gtk::prelude::*;
 use std::borrow::Borrow;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::f64::consts::PI;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 const PROGRAM_NAME: &str = "Carcassone Evolved";
-const SCALE: f64 = 0.5;
 const WINDOW_SIZE: i32 = 645;
+const PANEL_WIDTH: i32 = 260;
+const SOLUTION_PATH: &str = "./solution.json";
 
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let command = parse_args(&args)
+        .unwrap_or_else(|error| exit_with_error(&format!("{}\n{}", error, usage())));
+    match command {
+        Command::Gui {
+            config,
+            board: Some(board_path),
+        } => {
+            let (cards_path, cards, board) =
+                load_board(&board_path).unwrap_or_else(|error| exit_with_error(&error));
+            run_gui(&args[..1], config, cards_path, cards, Some(board));
+        }
+        Command::Gui {
+            config,
+            board: None,
+        } => {
+            let cards = load_default_cards();
+            validate_config(&config, &cards);
+            run_gui(&args[..1], config, PathBuf::from(CARDS_PATH), cards, None);
+        }
+        Command::Resume {
+            resume,
+            options,
+            output,
+            checkpoint,
+        } => {
+            let resume = load_checkpoint(&resume).unwrap_or_else(|error| exit_with_error(&error));
+            let config = options
+                .apply(resume.config.clone())
+                .unwrap_or_else(|error| exit_with_error(&format!("{}\n{}", error, usage())));
+            validate_config(&config, &resume.cards);
+            run_headless(
+                &config,
+                &resume.cards_path,
+                &resume.cards,
+                &output,
+                Some(&checkpoint),
+                Some(resume.state),
+            )
+            .unwrap_or_else(|error| exit_with_error(&error));
+        }
+        Command::Headless {
+            config,
+            output,
+            checkpoint,
+        } => {
+            let cards = load_default_cards();
+            validate_config(&config, &cards);
+            run_headless(
+                &config,
+                Path::new(CARDS_PATH),
+                &cards,
+                &output,
+                checkpoint.as_deref(),
+                None,
+            )
+            .unwrap_or_else(|error| exit_with_error(&error));
+        }
+        Command::Export { board, output } => {
+            let (cards_path, cards, board) =
+                load_board(&board).unwrap_or_else(|error| exit_with_error(&error));
+            let renderer = BoardRenderer::new(&cards, &cards_path)
+                .unwrap_or_else(|error| exit_with_error(&error));
+            renderer
+                .export(&board, &output, false)
+                .unwrap_or_else(|error| exit_with_error(&error));
+            println!("board exported to {}", output.display());
+        }
+        Command::Dimacs { config, output } => {
+            let cards = load_default_cards();
+            validate_config(&config, &cards);
+            PerfectBoard::encode(&config, &cards)
+                .write_dimacs(&output)
+                .unwrap_or_else(|error| exit_with_error(&error));
+            println!("problem written to {}", output.display());
+        }
+    }
+}
+
+fn load_default_cards() -> Vec<Card> {
+    load_cards(Path::new(CARDS_PATH)).unwrap_or_else(|error| exit_with_error(&error))
+}
+
+fn validate_config(config: &EvolutionConfig, cards: &Vec<Card>) {
+    if let Err(error) = config.validate(cards.len()) {
+        exit_with_error(&error);
+    }
+}
+
+fn exit_with_error(error: &str) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+fn run_gui(
+    args: &[String],
+    config: EvolutionConfig,
+    cards_path: PathBuf,
+    cards: Vec<Card>,
+    board: Option<Board>,
+) {
     let app = gtk::Application::new(
         Some("com.romanzes.carcassone"),
         ApplicationFlags::HANDLES_OPEN | ApplicationFlags::NON_UNIQUE,
     )
     .unwrap();
-    app.connect_startup(build_ui);
+    app.connect_startup(move |app| build_ui(app, &config, &cards_path, &cards, &board));
     app.connect_activate(|_| ());
-    app.run(&std::env::args().collect::<Vec<_>>());
+    app.run(args);
 }
 
-fn load_cards() -> Vec<Card> {
-    let cards_file = std::fs::File::open(Path::new("./resources/cards.json")).unwrap();
-    serde_json::from_reader(cards_file).unwrap()
-}
+fn build_ui(
+    app: &gtk::Application,
+    config: &EvolutionConfig,
+    cards_path: &Path,
+    cards: &Vec<Card>,
+    board: &Option<Board>,
+) {
+    let visualizer = GtkVisualizer::new(config, cards_path, cards, app);
+    if let Some(board) = board {
+        let fitness = Evaluator::new(&config.weights, cards)
+            .evaluate_board(cards, board)
+            .unwrap_or_else(|error| exit_with_error(&error));
+        visualizer.show(RatedBoard {
+            generation: 0,
+            fitness,
+            board: board.clone(),
+        });
+        return;
+    }
 
-fn build_ui(app: &gtk::Application) {
     let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-    std::thread::spawn(move || {
-        // TODO find a way to load cards only once and share them among threads
-        let cards = load_cards();
-        start_evolution(&cards, &tx);
+    std::thread::spawn({
+        let config = config.clone();
+        let cards = cards.clone();
+        move || {
+            solve(&config, &cards, &tx);
+        }
     });
 
-    let cards = load_cards();
-    let visualizer = GtkVisualizer::new(&cards, app);
-
-    rx.attach(None, move |board| {
-        visualizer.display_result(board.score, board.board);
+    rx.attach(None, move |result| {
+        visualizer.display_result(result);
         Continue(true)
     });
 }
 
+impl ProgressSink for glib::Sender<RatedBoard> {
+    fn report(&self, result: RatedBoard) {
+        // Sending fails once the window is closed and the receiver is gone, which is fine.
+        self.send(result).ok();
+    }
+}
+
 #[derive(Debug)]
 pub struct State {
     app: gtk::Application,
@@ -68,19 +212,21 @@ pub struct State {
 pub struct CanvasSurface {
     score: usize,
     board: Board,
-    card_images: HashMap<Card, Pixbuf>,
+    renderer: BoardRenderer,
     surface: ImageSurface,
+    show_violations: bool,
 }
 
 impl CanvasSurface {
-    pub fn new(card_images: HashMap<Card, Pixbuf>) -> CanvasSurface {
+    pub fn new(renderer: BoardRenderer) -> CanvasSurface {
         let surface =
             cairo::ImageSurface::create(cairo::Format::ARgb32, WINDOW_SIZE, WINDOW_SIZE).unwrap();
         CanvasSurface {
             score: 0,
-            board: create_empty_board(),
-            card_images,
+            board: Board::default(),
+            renderer,
             surface,
+            show_violations: false,
         }
     }
 
@@ -90,73 +236,206 @@ impl CanvasSurface {
     }
 
     pub fn draw(&self, context: &cairo::Context) {
-        context.scale(SCALE, SCALE);
-        context.set_source_rgb(1.0, 1.0, 1.0);
-        context.paint();
-        context.fill();
-        for x in 0..self.board.width {
-            for y in 0..self.board.height {
-                if let Some(cell) = &self.board.cells[x][y] {
-                    let image = &self.card_images[&cell.card];
-                    context.save();
-                    context.translate((x as f64 + 0.5) * 86.0, (y as f64 + 0.5) * 86.0);
-                    let rotation = match cell.card_side {
-                        CardSide::LEFT => 0.0,
-                        CardSide::TOP => PI / 2.0,
-                        CardSide::RIGHT => PI,
-                        CardSide::BOTTOM => PI * 3.0 / 2.0,
-                    };
-                    context.rotate(rotation);
-                    context.set_source_pixbuf(image, -43.0, -43.0);
-                    context.paint();
-                    context.fill();
-                    context.restore();
-                }
-            }
-        }
+        let size = self.board.width().max(self.board.height()).max(1);
+        let scale = WINDOW_SIZE as f64 / (size as f64 * TILE_SIZE);
+        context.scale(scale, scale);
+        self.renderer
+            .draw(context, &self.board, self.show_violations);
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), String> {
+        self.renderer
+            .export(&self.board, path, self.show_violations)
     }
 }
 
 pub trait Visualizer {
-    fn display_result(&self, score: usize, board: Board);
+    fn display_result(&self, result: RatedBoard);
+}
+
+struct StatsPanel {
+    grid: gtk::Grid,
+    score: gtk::Label,
+    components: Vec<gtk::Label>,
+    generation: gtk::Label,
+    elapsed: gtk::Label,
+    speed: gtk::Label,
+}
+
+impl StatsPanel {
+    fn new(config: &EvolutionConfig) -> StatsPanel {
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(4);
+        grid.set_column_spacing(12);
+        grid.set_border_width(12);
+        let mut row = 0;
+        let mut add_row = |name: &str| {
+            let name_label = gtk::Label::new(Some(name));
+            name_label.set_halign(gtk::Align::Start);
+            let value_label = gtk::Label::new(Some("-"));
+            value_label.set_halign(gtk::Align::End);
+            grid.attach(&name_label, 0, row, 1, 1);
+            grid.attach(&value_label, 1, row, 1, 1);
+            row += 1;
+            value_label
+        };
+        let score = add_row("score");
+        let components = fitness_terms(&config.weights)
+            .iter()
+            .map(|weighted_term| add_row(&weighted_term.term.name().replace('_', " ")))
+            .collect();
+        let generation = add_row("generation");
+        let elapsed = add_row("elapsed time");
+        let speed = add_row("generations/s");
+        StatsPanel {
+            grid,
+            score,
+            components,
+            generation,
+            elapsed,
+            speed,
+        }
+    }
+
+    fn update(&self, result: &RatedBoard, elapsed: Duration) {
+        self.score.set_text(&result.fitness.score.to_string());
+        for (label, component) in self.components.iter().zip(&result.fitness.breakdown) {
+            label.set_text(&component.value.to_string());
+        }
+        self.generation.set_text(&result.generation.to_string());
+        let seconds = elapsed.as_secs();
+        self.elapsed
+            .set_text(&format!("{}:{:02}", seconds / 60, seconds % 60));
+        let speed = (result.generation + 1) as f64 / elapsed.as_secs_f64();
+        self.speed.set_text(&format!("{:.1}", speed));
+    }
 }
 
 struct GtkVisualizer {
     state: Rc<State>,
     drawing_area: gtk::DrawingArea,
+    stats_panel: StatsPanel,
+    started: Instant,
+    cards_path: PathBuf,
+    cards: Vec<Card>,
 }
 
 impl GtkVisualizer {
-    fn new(cards: &Vec<Card>, app: &gtk::Application) -> GtkVisualizer {
+    fn new(
+        config: &EvolutionConfig,
+        cards_path: &Path,
+        cards: &Vec<Card>,
+        app: &gtk::Application,
+    ) -> GtkVisualizer {
+        let renderer =
+            BoardRenderer::new(cards, cards_path).unwrap_or_else(|error| exit_with_error(&error));
         let window = gtk::ApplicationWindow::new(app);
 
-        let card_images = cards
-            .iter()
-            .map(|card| {
-                let file_name = format!("./resources/{}", card.pic);
-                let image = Pixbuf::new_from_file(file_name).unwrap();
-                (card.clone(), image)
-            })
-            .collect::<HashMap<Card, Pixbuf>>();
         let state: Rc<State> = Rc::new(State {
             app: app.clone(),
             window: window.clone(),
-            canvas_surface: RefCell::new(CanvasSurface::new(card_images)),
+            canvas_surface: RefCell::new(CanvasSurface::new(renderer)),
         });
 
         state.window.set_title(PROGRAM_NAME);
-        state.window.set_default_size(WINDOW_SIZE, WINDOW_SIZE);
+        state
+            .window
+            .set_default_size(WINDOW_SIZE + PANEL_WIDTH, WINDOW_SIZE);
         let drawing_area = GtkVisualizer::build_drawing_area(&state);
-        state.window.add(&drawing_area);
+        let stats_panel = StatsPanel::new(config);
+        let violations_toggle = GtkVisualizer::build_violations_toggle(&state, &drawing_area);
+        let side_panel = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        side_panel.pack_start(&stats_panel.grid, false, false, 0);
+        side_panel.pack_start(&violations_toggle, false, false, 0);
+        let content = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        content.pack_start(&drawing_area, false, false, 0);
+        content.pack_start(&side_panel, true, true, 0);
+        let layout = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        layout.pack_start(&GtkVisualizer::build_menu_bar(&state), false, false, 0);
+        layout.pack_start(&content, true, true, 0);
+        state.window.add(&layout);
 
         state.window.show_all();
 
         GtkVisualizer {
             state,
             drawing_area,
+            stats_panel,
+            started: Instant::now(),
+            cards_path: cards_path.to_path_buf(),
+            cards: cards.clone(),
         }
     }
 
+    fn show(&self, result: RatedBoard) {
+        let score = result.fitness.score;
+        self.stats_panel.update(&result, self.started.elapsed());
+        self.state
+            .window
+            .borrow()
+            .set_title(format!("{}: {}", PROGRAM_NAME, score).as_str());
+        self.state
+            .canvas_surface
+            .borrow_mut()
+            .update(score, result.board);
+        self.drawing_area.queue_draw();
+    }
+
+    fn build_menu_bar(state: &Rc<State>) -> gtk::MenuBar {
+        let export_item = gtk::MenuItem::new_with_label("Export image…");
+        export_item.connect_activate({
+            let state = state.clone();
+            move |_| GtkVisualizer::export_image(&state)
+        });
+        let file_menu = gtk::Menu::new();
+        file_menu.append(&export_item);
+        let file_item = gtk::MenuItem::new_with_label("File");
+        file_item.set_submenu(Some(&file_menu));
+        let menu_bar = gtk::MenuBar::new();
+        menu_bar.append(&file_item);
+        menu_bar
+    }
+
+    fn export_image(state: &Rc<State>) {
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Export image"),
+            Some(&state.window),
+            gtk::FileChooserAction::Save,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Export", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_current_name("board.png");
+        dialog.set_do_overwrite_confirmation(true);
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.close();
+        if let (gtk::ResponseType::Accept, Some(path)) = (response, path) {
+            match state.canvas_surface.borrow().export(&path) {
+                Ok(()) => println!("board exported to {}", path.display()),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
+
+    fn build_violations_toggle(
+        state: &Rc<State>,
+        drawing_area: &gtk::DrawingArea,
+    ) -> gtk::CheckButton {
+        let toggle = gtk::CheckButton::new_with_label("Show violations");
+        toggle.set_border_width(12);
+        toggle.connect_toggled({
+            let state = state.clone();
+            let drawing_area = drawing_area.clone();
+            move |toggle| {
+                state.canvas_surface.borrow_mut().show_violations = toggle.get_active();
+                drawing_area.queue_draw();
+            }
+        });
+        toggle
+    }
+
     fn build_drawing_area(state: &Rc<State>) -> gtk::DrawingArea {
         let area = gtk::DrawingArea::new();
         area.set_size_request(WINDOW_SIZE, WINDOW_SIZE);
@@ -173,14 +452,16 @@ impl GtkVisualizer {
     }
 
     fn display_board(board: &Board) {
-        for y in 0..board.height {
-            for _ in 0..board.width {
+        let board = board.normalized();
+        let (width, height) = (board.width() as i32, board.height() as i32);
+        for y in 0..height {
+            for _ in 0..width {
                 print!("┼──────────");
             }
             println!();
 
-            for x in 0..board.width {
-                let side = match top_side(&board.cells[x][y]) {
+            for x in 0..width {
+                let side = match top_side(board.get(x, y)) {
                     TerrainType::FIELD => "          ",
                     TerrainType::ROAD => "    ██    ",
                     TerrainType::TOWN => "██████████",
@@ -189,13 +470,13 @@ impl GtkVisualizer {
             }
             println!();
 
-            for x in 0..board.width {
-                let left_side = match left_side(&board.cells[x][y]) {
+            for x in 0..width {
+                let left_side = match left_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "  ",
                     TerrainType::TOWN => "██",
                 };
-                let right_side = match right_side(&board.cells[x][y]) {
+                let right_side = match right_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "  ",
                     TerrainType::TOWN => "██",
@@ -204,13 +485,13 @@ impl GtkVisualizer {
             }
             println!();
 
-            for x in 0..board.width {
-                let left_side = match left_side(&board.cells[x][y]) {
+            for x in 0..width {
+                let left_side = match left_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "██",
                     TerrainType::TOWN => "██",
                 };
-                let right_side = match right_side(&board.cells[x][y]) {
+                let right_side = match right_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "██",
                     TerrainType::TOWN => "██",
@@ -219,13 +500,13 @@ impl GtkVisualizer {
             }
             println!();
 
-            for x in 0..board.width {
-                let left_side = match left_side(&board.cells[x][y]) {
+            for x in 0..width {
+                let left_side = match left_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "  ",
                     TerrainType::TOWN => "██",
                 };
-                let right_side = match right_side(&board.cells[x][y]) {
+                let right_side = match right_side(board.get(x, y)) {
                     TerrainType::FIELD => "  ",
                     TerrainType::ROAD => "  ",
                     TerrainType::TOWN => "██",
@@ -234,8 +515,8 @@ impl GtkVisualizer {
             }
             println!();
 
-            for x in 0..board.width {
-                let side = match bottom_side(&board.cells[x][y]) {
+            for x in 0..width {
+                let side = match bottom_side(board.get(x, y)) {
                     TerrainType::FIELD => "          ",
                     TerrainType::ROAD => "    ██    ",
                     TerrainType::TOWN => "██████████",
@@ -248,18 +529,16 @@ impl GtkVisualizer {
 }
 
 impl Visualizer for GtkVisualizer {
-    fn display_result(&self, score: usize, board: Board) {
-        println!("best result: {}", score);
-        if score == 0 {
-            GtkVisualizer::display_board(&board);
-            let serialized = serde_json::to_string(&board).unwrap();
-            println!("{:?}", serialized);
+    fn display_result(&self, result: RatedBoard) {
+        println!("best result: {}", result.fitness);
+        if result.fitness.score == 0 {
+            GtkVisualizer::display_board(&result.board);
+            let solution_path = Path::new(SOLUTION_PATH);
+            match save_board(solution_path, &result.board, &self.cards_path, &self.cards) {
+                Ok(()) => println!("solution saved to {}", solution_path.display()),
+                Err(error) => eprintln!("{}", error),
+            }
         }
-        self.state
-            .window
-            .borrow()
-            .set_title(format!("{}: {}", PROGRAM_NAME, score).as_str());
-        self.state.canvas_surface.borrow_mut().update(score, board);
-        self.drawing_area.queue_draw();
+        self.show(result);
     }
 }
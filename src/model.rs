@@ -1,11 +1,80 @@
 use crate::model::TerrainType::FIELD;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const CARDS_PATH: &str = "./resources/cards.json";
+
+pub fn load_cards(path: &Path) -> Result<Vec<Card>, String> {
+    let cards_file = std::fs::File::open(path)
+        .map_err(|error| format!("cannot open {}: {}", path.display(), error))?;
+    serde_json::from_reader(cards_file)
+        .map_err(|error| format!("cannot parse {}: {}", path.display(), error))
+}
+
+/// Tiles keyed by their position. Positions may be negative, as a board can grow in every
+/// direction; `normalized` moves it back to the origin for display.
+#[derive(Clone, Debug, Default)]
 pub struct Board {
-    pub width: usize,
-    pub height: usize,
-    pub cells: Vec<Vec<Option<Cell>>>,
+    pub cells: BTreeMap<Pos, Cell>,
+}
+
+impl Board {
+    pub fn get(&self, x: i32, y: i32) -> Option<&Cell> {
+        self.cells.get(&Pos { x, y })
+    }
+
+    pub fn insert(&mut self, cell: Cell) {
+        self.cells.insert(cell.pos.clone(), cell);
+    }
+
+    /// The top left and bottom right corners of the smallest rectangle around the tiles.
+    pub fn bounds(&self) -> Option<(Pos, Pos)> {
+        let mut positions = self.cells.keys();
+        let first = positions.next()?.clone();
+        Some(positions.fold((first.clone(), first), |(min, max), pos| {
+            (
+                Pos {
+                    x: min.x.min(pos.x),
+                    y: min.y.min(pos.y),
+                },
+                Pos {
+                    x: max.x.max(pos.x),
+                    y: max.y.max(pos.y),
+                },
+            )
+        }))
+    }
+
+    pub fn width(&self) -> usize {
+        self.bounds()
+            .map_or(0, |(min, max)| (max.x - min.x + 1) as usize)
+    }
+
+    pub fn height(&self) -> usize {
+        self.bounds()
+            .map_or(0, |(min, max)| (max.y - min.y + 1) as usize)
+    }
+
+    /// The same tiles moved so that the rectangle around them starts at (0, 0).
+    pub fn normalized(&self) -> Board {
+        let min = match self.bounds() {
+            Some((min, _)) => min,
+            None => return self.clone(),
+        };
+        let mut board = Board::default();
+        for cell in self.cells.values() {
+            board.insert(Cell {
+                pos: Pos {
+                    x: cell.pos.x - min.x,
+                    y: cell.pos.y - min.y,
+                },
+                card: cell.card.clone(),
+                card_side: cell.card_side.clone(),
+            });
+        }
+        board
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Hash, PartialEq, Eq)]
@@ -62,38 +131,38 @@ impl Cell {
     }
 }
 
-pub fn left_side(cell: &Option<Cell>) -> TerrainType {
+pub fn left_side(cell: Option<&Cell>) -> TerrainType {
     match cell {
         Some(cell) => cell.left(),
         None => FIELD,
     }
 }
 
-pub fn top_side(cell: &Option<Cell>) -> TerrainType {
+pub fn top_side(cell: Option<&Cell>) -> TerrainType {
     match cell {
         Some(cell) => cell.top(),
         None => FIELD,
     }
 }
 
-pub fn right_side(cell: &Option<Cell>) -> TerrainType {
+pub fn right_side(cell: Option<&Cell>) -> TerrainType {
     match cell {
         Some(cell) => cell.right(),
         None => FIELD,
     }
 }
 
-pub fn bottom_side(cell: &Option<Cell>) -> TerrainType {
+pub fn bottom_side(cell: Option<&Cell>) -> TerrainType {
     match cell {
         Some(cell) => cell.bottom(),
         None => FIELD,
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
 pub struct Pos {
-    pub x: usize,
-    pub y: usize,
+    pub x: i32,
+    pub y: i32,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
@@ -143,7 +212,14 @@ pub struct Struct {
     pub terrain: TerrainType,
     #[serde(default, deserialize_with = "default_from_null")]
     pub sides: Vec<CardSide>,
+    #[serde(default, deserialize_with = "default_from_null")]
     pub value: usize,
+    /// Half-edges covered by a field, since roads split a side between two fields.
+    #[serde(default, deserialize_with = "default_from_null")]
+    pub half_sides: Vec<HalfSide>,
+    /// Indices of the town structs of the same card that border this field.
+    #[serde(default, deserialize_with = "default_from_null")]
+    pub towns: Vec<usize>,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
@@ -172,6 +248,45 @@ impl CardSide {
     }
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum HalfSide {
+    LEFT_BOTTOM,
+    LEFT_TOP,
+    TOP_LEFT,
+    TOP_RIGHT,
+    RIGHT_TOP,
+    RIGHT_BOTTOM,
+    BOTTOM_RIGHT,
+    BOTTOM_LEFT,
+}
+
+pub const CLOCKWISE_HALF_SIDES: [HalfSide; 8] = [
+    HalfSide::LEFT_BOTTOM,
+    HalfSide::LEFT_TOP,
+    HalfSide::TOP_LEFT,
+    HalfSide::TOP_RIGHT,
+    HalfSide::RIGHT_TOP,
+    HalfSide::RIGHT_BOTTOM,
+    HalfSide::BOTTOM_RIGHT,
+    HalfSide::BOTTOM_LEFT,
+];
+
+impl HalfSide {
+    pub fn get_opposite(&self) -> HalfSide {
+        match self {
+            HalfSide::LEFT_BOTTOM => HalfSide::RIGHT_BOTTOM,
+            HalfSide::LEFT_TOP => HalfSide::RIGHT_TOP,
+            HalfSide::TOP_LEFT => HalfSide::BOTTOM_LEFT,
+            HalfSide::TOP_RIGHT => HalfSide::BOTTOM_RIGHT,
+            HalfSide::RIGHT_TOP => HalfSide::LEFT_TOP,
+            HalfSide::RIGHT_BOTTOM => HalfSide::LEFT_BOTTOM,
+            HalfSide::BOTTOM_RIGHT => HalfSide::TOP_RIGHT,
+            HalfSide::BOTTOM_LEFT => HalfSide::TOP_LEFT,
+        }
+    }
+}
+
 impl std::fmt::Display for TerrainType {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -0,0 +1,211 @@
+use crate::algorithm::rearrange_overlaps;
+use crate::carcassone::{extract_clusters, fill_board};
+use crate::config::{EvolutionConfig, MutationRates};
+use crate::evolution::{random_card_side, EvolutionRng};
+use crate::model::{Board, CardSide, Cell, Pos};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::BTreeSet;
+
+const CARD_SIDES: [CardSide; 4] = [
+    CardSide::LEFT,
+    CardSide::TOP,
+    CardSide::RIGHT,
+    CardSide::BOTTOM,
+];
+const CLUSTER_MOVE_ATTEMPTS: usize = 10;
+
+/// Changes the cells of an offspring in place. These are the cells an algorithm stores, so
+/// several of them may share a position; mutations that look for free positions see the board
+/// after overlaps are rearranged, and move the tiles where they are shown on it.
+pub trait Mutation {
+    fn mutate(&self, config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>);
+}
+
+/// Moves a tile to a random position with a random rotation.
+struct Teleport;
+
+impl Mutation for Teleport {
+    fn mutate(&self, config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        let index = rng.gen_range(0, cells.len());
+        cells[index].card_side = random_card_side(rng);
+        cells[index].pos = Pos {
+            x: rng.gen_range(0, config.board_size as i32),
+            y: rng.gen_range(0, config.board_size as i32),
+        };
+    }
+}
+
+struct Rotate;
+
+impl Mutation for Rotate {
+    fn mutate(&self, _config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        let index = rng.gen_range(0, cells.len());
+        let card_side = CARD_SIDES
+            .iter()
+            .filter(|side| **side != cells[index].card_side)
+            .cloned()
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .cloned()
+            .unwrap();
+        cells[index].card_side = card_side;
+    }
+}
+
+struct Swap;
+
+impl Mutation for Swap {
+    fn mutate(&self, _config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        if cells.len() < 2 {
+            return;
+        }
+        let index1 = rng.gen_range(0, cells.len());
+        let mut index2 = rng.gen_range(0, cells.len() - 1);
+        if index2 >= index1 {
+            index2 += 1;
+        }
+        let pos1 = cells[index1].pos.clone();
+        cells[index1].pos = cells[index2].pos.clone();
+        cells[index2].pos = pos1;
+    }
+}
+
+/// Moves a tile to one of the free positions next to it.
+struct Shift;
+
+impl Mutation for Shift {
+    fn mutate(&self, _config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        let arranged_cells = rearrange_overlaps(cells);
+        let board = fill_board(&arranged_cells);
+        let index = rng.gen_range(0, cells.len());
+        let free_positions: Vec<Pos> = CARD_SIDES
+            .iter()
+            .map(|side| neighbour_pos(&arranged_cells[index].pos, side))
+            .filter(|pos| !board.cells.contains_key(pos))
+            .collect();
+        if let Some(pos) = free_positions.choose(rng) {
+            cells[index].pos = pos.clone();
+        }
+    }
+}
+
+/// Moves a whole group of connected tiles by the same offset, as long as it lands on free
+/// positions.
+struct MoveCluster;
+
+impl Mutation for MoveCluster {
+    fn mutate(&self, config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        let arranged_cells = rearrange_overlaps(cells);
+        let board = fill_board(&arranged_cells);
+        let clusters = extract_clusters(&board);
+        let cluster = clusters.choose(rng).unwrap();
+        let size = config.board_size as i32;
+        for _ in 0..CLUSTER_MOVE_ATTEMPTS {
+            let dx = rng.gen_range(-size + 1, size);
+            let dy = rng.gen_range(-size + 1, size);
+            let target = |pos: &Pos| Pos {
+                x: pos.x + dx,
+                y: pos.y + dy,
+            };
+            let fits = cluster.cells.iter().all(|cell| {
+                board
+                    .cells
+                    .get(&target(&cell.pos))
+                    .map_or(true, |other| cluster.cells.contains(other))
+            });
+            if fits {
+                for (cell, arranged) in cells.iter_mut().zip(&arranged_cells) {
+                    if cluster.cells.iter().any(|moved| moved.pos == arranged.pos) {
+                        cell.pos = target(&arranged.pos);
+                    }
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Moves a tile to a free position and rotation where every edge it shares with another tile
+/// matches. Only free positions next to other tiles are considered.
+struct Reseat;
+
+impl Mutation for Reseat {
+    fn mutate(&self, _config: &EvolutionConfig, rng: &mut EvolutionRng, cells: &mut Vec<Cell>) {
+        let index = rng.gen_range(0, cells.len());
+        let arranged_cells = rearrange_overlaps(cells);
+        let mut board = fill_board(&arranged_cells);
+        let cell = arranged_cells[index].clone();
+        board.cells.remove(&cell.pos);
+        let free_positions: BTreeSet<Pos> = board
+            .cells
+            .keys()
+            .flat_map(|pos| CARD_SIDES.iter().map(move |side| neighbour_pos(pos, side)))
+            .filter(|pos| !board.cells.contains_key(pos))
+            .collect();
+        let mut seats = vec![];
+        for pos in free_positions {
+            for card_side in CARD_SIDES.iter() {
+                let candidate = Cell {
+                    pos: pos.clone(),
+                    card: cell.card.clone(),
+                    card_side: card_side.clone(),
+                };
+                if fits_neighbours(&board, &candidate) {
+                    seats.push((candidate.pos, candidate.card_side));
+                }
+            }
+        }
+        if let Some((pos, card_side)) = seats.choose(rng) {
+            cells[index].pos = pos.clone();
+            cells[index].card_side = card_side.clone();
+        }
+    }
+}
+
+fn fits_neighbours(board: &Board, cell: &Cell) -> bool {
+    let mut neighbour_count = 0;
+    for side in CARD_SIDES.iter() {
+        if let Some(neighbour) = board.cells.get(&neighbour_pos(&cell.pos, side)) {
+            if cell.get_side(side) != neighbour.get_side(&side.get_opposite()) {
+                return false;
+            }
+            neighbour_count += 1;
+        }
+    }
+    neighbour_count > 0
+}
+
+fn neighbour_pos(pos: &Pos, side: &CardSide) -> Pos {
+    let (x, y) = match side {
+        CardSide::LEFT => (pos.x - 1, pos.y),
+        CardSide::TOP => (pos.x, pos.y - 1),
+        CardSide::RIGHT => (pos.x + 1, pos.y),
+        CardSide::BOTTOM => (pos.x, pos.y + 1),
+    };
+    Pos { x, y }
+}
+
+pub struct WeightedMutation {
+    pub mutation: Box<dyn Mutation>,
+    pub probability: f64,
+}
+
+pub fn mutations(rates: &MutationRates) -> Vec<WeightedMutation> {
+    let mutations: Vec<(f64, Box<dyn Mutation>)> = vec![
+        (rates.teleport, Box::new(Teleport)),
+        (rates.rotate, Box::new(Rotate)),
+        (rates.swap, Box::new(Swap)),
+        (rates.shift, Box::new(Shift)),
+        (rates.move_cluster, Box::new(MoveCluster)),
+        (rates.reseat, Box::new(Reseat)),
+    ];
+    mutations
+        .into_iter()
+        .filter(|(probability, _)| *probability > 0.0)
+        .map(|(probability, mutation)| WeightedMutation {
+            mutation,
+            probability,
+        })
+        .collect()
+}
@@ -0,0 +1,489 @@
+use crate::carcassone::{extract_clusters, fill_board};
+use crate::config::EvolutionConfig;
+use crate::fitness::Evaluator;
+use crate::model::{Board, Card, CardSide, Cell, Pos, TerrainType};
+use crate::sat::{write_dimacs, Lit, SatResult, SatSolver};
+use crate::solver::{RatedBoard, Solver};
+use std::path::Path;
+
+const SIDES: [CardSide; 4] = [
+    CardSide::LEFT,
+    CardSide::TOP,
+    CardSide::RIGHT,
+    CardSide::BOTTOM,
+];
+const TERRAINS: [TerrainType; 3] = [TerrainType::ROAD, TerrainType::FIELD, TerrainType::TOWN];
+/// Conflicts analysed by the SAT solver for each generation of the generation limit.
+const CONFLICTS_PER_GENERATION: usize = 1000;
+
+/// Puts card `card` at `pos` with the rotation `card_side`.
+struct Placement {
+    card: usize,
+    pos: Pos,
+    card_side: CardSide,
+}
+
+/// The problem of placing every card on the board so that all edges match and every town is
+/// closed, as clauses over one variable per placement. Auxiliary variables tell whether a
+/// position is occupied and which terrain each side of it shows.
+///
+/// Whether the tiles form a single cluster is left out, as it needs far more clauses than the
+/// rest: the solver instead forbids every disconnected model it finds and searches again.
+///
+/// The board is limited to a `board_size`×`board_size` box and no town may face its border, so
+/// an unsatisfiable problem only rules out boards within the box. A box as wide as there are
+/// cards fits every single cluster, which makes the answer complete.
+pub struct PerfectBoard {
+    width: usize,
+    height: usize,
+    placements: Vec<Placement>,
+    occupied: Vec<Lit>,
+    var_count: usize,
+    clauses: Vec<Vec<Lit>>,
+}
+
+impl PerfectBoard {
+    pub fn encode(config: &EvolutionConfig, cards: &Vec<Card>) -> PerfectBoard {
+        let (width, height) = (config.board_size, config.board_size);
+        let mut placements = vec![];
+        for (card_index, card) in cards.iter().enumerate() {
+            let mut rotations: Vec<(CardSide, Vec<TerrainType>)> = vec![];
+            for card_side in SIDES.iter() {
+                let cell = Cell {
+                    pos: Pos { x: 0, y: 0 },
+                    card: card.clone(),
+                    card_side: card_side.clone(),
+                };
+                let sides: Vec<TerrainType> =
+                    SIDES.iter().map(|side| cell.get_side(side)).collect();
+                // Symmetric cards look the same in several rotations.
+                if rotations.iter().all(|(_, other)| *other != sides) {
+                    rotations.push((card_side.clone(), sides));
+                }
+            }
+            for x in 0..width {
+                for y in 0..height {
+                    for (card_side, _) in &rotations {
+                        placements.push(Placement {
+                            card: card_index,
+                            pos: Pos {
+                                x: x as i32,
+                                y: y as i32,
+                            },
+                            card_side: card_side.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        let mut board = PerfectBoard {
+            width,
+            height,
+            var_count: placements.len(),
+            placements,
+            occupied: vec![],
+            clauses: vec![],
+        };
+        board.add_constraints(cards);
+        board
+    }
+
+    pub fn write_dimacs(&self, path: &Path) -> Result<(), String> {
+        let mut comments = vec![
+            format!(
+                "every card placed once within a {}x{} box",
+                self.width, self.height
+            ),
+            "with matching edges and closed towns; a single cluster is not required".to_owned(),
+            "no town faces the border of the box, so UNSAT only rules out boards within it"
+                .to_owned(),
+        ];
+        for (index, placement) in self.placements.iter().enumerate() {
+            comments.push(format!(
+                "{} card {} x {} y {} rotation {:?}",
+                index + 1,
+                placement.card,
+                placement.pos.x,
+                placement.pos.y,
+                placement.card_side
+            ));
+        }
+        write_dimacs(path, self.var_count, &self.clauses, &comments)
+    }
+
+    fn add_constraints(&mut self, cards: &Vec<Card>) {
+        let positions = self.width * self.height;
+        let mut by_card: Vec<Vec<Lit>> = vec![vec![]; cards.len()];
+        let mut by_position: Vec<Vec<Lit>> = vec![vec![]; positions];
+        let mut by_card_position: Vec<Vec<Vec<Lit>>> = vec![vec![vec![]; positions]; cards.len()];
+        for (index, placement) in self.placements.iter().enumerate() {
+            let var = index as Lit + 1;
+            let position = self.position(&placement.pos);
+            by_card[placement.card].push(var);
+            by_position[position].push(var);
+            by_card_position[placement.card][position].push(var);
+        }
+        for vars in &by_card {
+            self.clauses.push(vars.clone());
+            self.at_most_one(vars);
+        }
+        // Identical cards are interchangeable, so each of them is put after the previous one.
+        // earlier[position] may only hold if the previous card lies before `position`.
+        for card in 1..cards.len() {
+            if cards[card] != cards[card - 1] {
+                continue;
+            }
+            let mut previous_earlier = None;
+            for position in 0..positions {
+                let earlier = self.new_var();
+                let mut justified = vec![-earlier];
+                justified.extend(previous_earlier);
+                if position > 0 {
+                    justified.extend(&by_card_position[card - 1][position - 1]);
+                }
+                self.clauses.push(justified);
+                for var in &by_card_position[card][position] {
+                    self.clauses.push(vec![-var, earlier]);
+                }
+                previous_earlier = Some(earlier);
+            }
+        }
+
+        self.occupied = (0..positions).map(|_| self.new_var()).collect();
+        let occupied = self.occupied.clone();
+        for position in 0..positions {
+            let vars = &by_position[position];
+            let mut any_placement = vec![-occupied[position]];
+            any_placement.extend(vars);
+            self.clauses.push(any_placement);
+            for var in vars {
+                self.clauses.push(vec![-var, occupied[position]]);
+            }
+            self.at_most_one(vars);
+        }
+        // Any board can be shifted until it touches the top and left borders.
+        self.clauses
+            .push((0..self.height).map(|y| occupied[y]).collect());
+        self.clauses
+            .push((0..self.width).map(|x| occupied[x * self.height]).collect());
+
+        // shows[position][side][terrain] holds when the tile at `position` has `terrain` on
+        // `side`.
+        let mut shows = vec![vec![vec![0; TERRAINS.len()]; SIDES.len()]; positions];
+        for position in 0..positions {
+            for side in 0..SIDES.len() {
+                for terrain in 0..TERRAINS.len() {
+                    shows[position][side][terrain] = self.new_var();
+                }
+            }
+        }
+        let mut supports = vec![vec![vec![]; TERRAINS.len()]; SIDES.len() * positions];
+        for (index, placement) in self.placements.iter().enumerate() {
+            let var = index as Lit + 1;
+            let position = self.position(&placement.pos);
+            let cell = Cell {
+                pos: placement.pos.clone(),
+                card: cards[placement.card].clone(),
+                card_side: placement.card_side.clone(),
+            };
+            for (side, card_side) in SIDES.iter().enumerate() {
+                let terrain = terrain_index(&cell.get_side(card_side));
+                self.clauses
+                    .push(vec![-var, shows[position][side][terrain]]);
+                supports[position * SIDES.len() + side][terrain].push(var);
+            }
+        }
+        for position in 0..positions {
+            for side in 0..SIDES.len() {
+                for terrain in 0..TERRAINS.len() {
+                    let mut supported = vec![-shows[position][side][terrain]];
+                    supported.extend(&supports[position * SIDES.len() + side][terrain]);
+                    self.clauses.push(supported);
+                }
+                let town = shows[position][side][terrain_index(&TerrainType::TOWN)];
+                match self.neighbour(position, side) {
+                    Some(neighbour) => {
+                        self.clauses.push(vec![-town, occupied[neighbour]]);
+                        let opposite = (side + 2) % SIDES.len();
+                        for terrain in 0..TERRAINS.len() {
+                            self.clauses.push(vec![
+                                -shows[position][side][terrain],
+                                -occupied[neighbour],
+                                shows[neighbour][opposite][terrain],
+                            ]);
+                        }
+                    }
+                    None => self.clauses.push(vec![-town]),
+                }
+            }
+        }
+    }
+
+    /// At most one of `vars` holds, with the sequential counter encoding.
+    fn at_most_one(&mut self, vars: &Vec<Lit>) {
+        if vars.len() < 2 {
+            return;
+        }
+        let mut previous = self.new_var();
+        self.clauses.push(vec![-vars[0], previous]);
+        for var in &vars[1..vars.len() - 1] {
+            let counter = self.new_var();
+            self.clauses.push(vec![-var, counter]);
+            self.clauses.push(vec![-previous, counter]);
+            self.clauses.push(vec![-var, -previous]);
+            previous = counter;
+        }
+        self.clauses.push(vec![-vars[vars.len() - 1], -previous]);
+    }
+
+    fn new_var(&mut self) -> Lit {
+        self.var_count += 1;
+        self.var_count as Lit
+    }
+
+    fn position(&self, pos: &Pos) -> usize {
+        pos.x as usize * self.height + pos.y as usize
+    }
+
+    fn neighbour(&self, position: usize, side: usize) -> Option<usize> {
+        let (x, y) = (position / self.height, position % self.height);
+        match side {
+            0 if x > 0 => Some(position - self.height),
+            1 if y > 0 => Some(position - 1),
+            2 if x < self.width - 1 => Some(position + self.height),
+            3 if y < self.height - 1 => Some(position + 1),
+            _ => None,
+        }
+    }
+
+    fn decode(&self, cards: &Vec<Card>, model: &Vec<bool>) -> Board {
+        let cells = self
+            .placements
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| model[*index])
+            .map(|(_, placement)| Cell {
+                pos: placement.pos.clone(),
+                card: cards[placement.card].clone(),
+                card_side: placement.card_side.clone(),
+            })
+            .collect();
+        fill_board(&cells)
+    }
+
+    /// Forbids the tiles of one cluster to occupy exactly these positions while none of the
+    /// positions around them is occupied, since the other tiles have to join it.
+    fn connectivity_cut(&self, cells: &Vec<Cell>) -> Vec<Lit> {
+        let positions: Vec<usize> = cells.iter().map(|cell| self.position(&cell.pos)).collect();
+        let mut cut: Vec<Lit> = positions
+            .iter()
+            .map(|position| -self.occupied[*position])
+            .collect();
+        for position in &positions {
+            for side in 0..SIDES.len() {
+                if let Some(neighbour) = self.neighbour(*position, side) {
+                    let lit = self.occupied[neighbour];
+                    if !positions.contains(&neighbour) && !cut.contains(&lit) {
+                        cut.push(lit);
+                    }
+                }
+            }
+        }
+        cut
+    }
+}
+
+/// Looks for a board where every card is placed, all edges match, every town is closed and the
+/// tiles form a single cluster. Until one is found, or when there is none, the cards are shown
+/// in rows instead.
+pub struct PerfectBoardSearch<'a> {
+    config: &'a EvolutionConfig,
+    cards: &'a Vec<Card>,
+    evaluator: Evaluator,
+    /// The problem and its solver, until the search is over.
+    search: Option<(PerfectBoard, SatSolver)>,
+    board: Option<Board>,
+    /// Why the search ended without a board.
+    status: Option<String>,
+    generation: usize,
+}
+
+impl<'a> PerfectBoardSearch<'a> {
+    pub fn new(config: &'a EvolutionConfig, cards: &'a Vec<Card>) -> PerfectBoardSearch<'a> {
+        let town_edges: usize = cards
+            .iter()
+            .map(|card| {
+                SIDES
+                    .iter()
+                    .filter(|side| card.get_terrain((*side).clone()) == TerrainType::TOWN)
+                    .count()
+            })
+            .sum();
+        let mut status = None;
+        let search = if town_edges % 2 == 1 {
+            status = Some(format!(
+                "the cards have {} town edges, so no board closes every town",
+                town_edges
+            ));
+            None
+        } else {
+            let problem = PerfectBoard::encode(config, cards);
+            let mut solver = SatSolver::new(problem.var_count);
+            for clause in &problem.clauses {
+                solver.add_clause(clause);
+            }
+            Some((problem, solver))
+        };
+        PerfectBoardSearch {
+            config,
+            cards,
+            evaluator: Evaluator::new(&config.weights, cards),
+            search,
+            board: None,
+            status,
+            generation: 0,
+        }
+    }
+}
+
+impl Solver for PerfectBoardSearch<'_> {
+    fn step(&mut self) -> RatedBoard {
+        if let Some((problem, solver)) = self.search.as_mut() {
+            loop {
+                match solver.solve(CONFLICTS_PER_GENERATION) {
+                    SatResult::Sat(model) => {
+                        let board = problem.decode(self.cards, &model);
+                        let clusters = extract_clusters(&board);
+                        if clusters.len() == 1 {
+                            self.board = Some(board);
+                            self.search = None;
+                            break;
+                        }
+                        for cluster in &clusters {
+                            solver.add_clause(&problem.connectivity_cut(&cluster.cells));
+                        }
+                    }
+                    SatResult::Unsat => {
+                        self.status = Some(format!(
+                            "no board within a {0}x{0} box places every card with matching \
+                             edges and closed towns",
+                            self.config.board_size
+                        ));
+                        self.search = None;
+                        break;
+                    }
+                    SatResult::Unknown => break,
+                }
+            }
+        }
+        self.generation += 1;
+        self.best().unwrap()
+    }
+
+    fn best(&self) -> Option<RatedBoard> {
+        let board = self
+            .board
+            .clone()
+            .unwrap_or_else(|| card_rows(self.config, self.cards));
+        Some(RatedBoard {
+            generation: self.generation.saturating_sub(1),
+            fitness: self.evaluator.evaluate_board(self.cards, &board).unwrap(),
+            board,
+        })
+    }
+
+    fn is_done(&self) -> bool {
+        let out_of_generations = self
+            .config
+            .max_generations
+            .map_or(false, |max_generations| self.generation >= max_generations);
+        self.search.is_none() || out_of_generations
+    }
+
+    fn status(&self) -> Option<String> {
+        self.status.clone()
+    }
+}
+
+fn card_rows(config: &EvolutionConfig, cards: &Vec<Card>) -> Board {
+    let cells = cards
+        .iter()
+        .enumerate()
+        .map(|(index, card)| Cell {
+            pos: Pos {
+                x: (index % config.board_size) as i32,
+                y: (index / config.board_size) as i32,
+            },
+            card: card.clone(),
+            card_side: CardSide::LEFT,
+        })
+        .collect();
+    fill_board(&cells)
+}
+
+fn terrain_index(terrain: &TerrainType) -> usize {
+    TERRAINS.iter().position(|other| other == terrain).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtracking::Backtracking;
+    use crate::carcassone::{find_non_matching_edges, find_unclosed_parts};
+    use crate::model::{load_cards, CARDS_PATH};
+
+    /// Whether the solver ends with a single cluster of every card, with matching edges and
+    /// closed towns.
+    fn finds_perfect_board(solver: &mut dyn Solver, card_count: usize) -> bool {
+        while !solver.is_done() {
+            solver.step();
+        }
+        let board = solver.best().unwrap().board;
+        board.cells.len() == card_count
+            && extract_clusters(&board).len() == 1
+            && find_non_matching_edges(&board).is_empty()
+            && find_unclosed_parts(&board, &TerrainType::TOWN).is_empty()
+    }
+
+    /// The backtracking solver explores every board, so both must agree on whether a perfect
+    /// board exists.
+    #[test]
+    fn agrees_with_backtracking() {
+        let all_cards = load_cards(Path::new(CARDS_PATH)).unwrap();
+        let subsets: Vec<(usize, Vec<usize>, bool)> = vec![
+            (4, vec![0, 1, 2, 3, 50, 51], true),
+            (4, vec![0, 7, 8, 50], false),
+            (
+                7,
+                vec![
+                    0, 1, 4, 5, 14, 15, 24, 32, 33, 34, 35, 50, 51, 52, 58, 59, 60, 61, 67, 71,
+                ],
+                true,
+            ),
+        ];
+        for (board_size, indices, perfect) in subsets {
+            let cards: Vec<Card> = indices
+                .iter()
+                .map(|index| all_cards[*index].clone())
+                .collect();
+            let config = EvolutionConfig {
+                board_size,
+                ..EvolutionConfig::default()
+            };
+            let mut search = PerfectBoardSearch::new(&config, &cards);
+            let mut backtracking = Backtracking::new(&config, &cards);
+            assert_eq!(
+                finds_perfect_board(&mut search, cards.len()),
+                perfect,
+                "sat on cards {:?}",
+                indices
+            );
+            assert_eq!(
+                finds_perfect_board(&mut backtracking, cards.len()),
+                perfect,
+                "backtracking on cards {:?}",
+                indices
+            );
+        }
+    }
+}
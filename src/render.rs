@@ -0,0 +1,158 @@
+use crate::carcassone::{extract_clusters, find_non_matching_edges, find_unclosed_parts, Edge};
+use crate::model::{Board, Card, CardSide, TerrainType};
+use gdk::prelude::GdkContextExt;
+use gdk_pixbuf::Pixbuf;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+
+pub const TILE_SIZE: f64 = 86.0;
+const CLUSTER_COLORS: [(f64, f64, f64); 8] = [
+    (0.12, 0.47, 0.71),
+    (0.17, 0.63, 0.17),
+    (0.58, 0.40, 0.74),
+    (0.55, 0.34, 0.29),
+    (0.89, 0.47, 0.76),
+    (0.50, 0.50, 0.50),
+    (0.74, 0.74, 0.13),
+    (0.09, 0.75, 0.81),
+];
+
+#[derive(Debug)]
+pub struct BoardRenderer {
+    card_images: HashMap<Card, Pixbuf>,
+}
+
+impl BoardRenderer {
+    /// Loads the card images, which lie next to the card set at `cards_path`.
+    pub fn new(cards: &Vec<Card>, cards_path: &Path) -> Result<BoardRenderer, String> {
+        let directory = match cards_path.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        };
+        let card_images = cards
+            .iter()
+            .map(|card| {
+                let file_name = directory.join(&card.pic);
+                Pixbuf::new_from_file(&file_name)
+                    .map(|image| (card.clone(), image))
+                    .map_err(|error| format!("cannot open {}: {}", file_name.display(), error))
+            })
+            .collect::<Result<HashMap<Card, Pixbuf>, String>>()?;
+        Ok(BoardRenderer { card_images })
+    }
+
+    /// Draws the board with every tile `TILE_SIZE` units wide, with the top left tile at the
+    /// origin.
+    pub fn draw(&self, context: &cairo::Context, board: &Board, show_violations: bool) {
+        let board = &board.normalized();
+        context.set_source_rgb(1.0, 1.0, 1.0);
+        context.paint();
+        context.fill();
+        for cell in board.cells.values() {
+            let image = &self.card_images[&cell.card];
+            context.save();
+            context.translate(
+                (cell.pos.x as f64 + 0.5) * TILE_SIZE,
+                (cell.pos.y as f64 + 0.5) * TILE_SIZE,
+            );
+            let rotation = match cell.card_side {
+                CardSide::LEFT => 0.0,
+                CardSide::TOP => PI * 3.0 / 2.0,
+                CardSide::RIGHT => PI,
+                CardSide::BOTTOM => PI / 2.0,
+            };
+            context.rotate(rotation);
+            context.set_source_pixbuf(image, -TILE_SIZE / 2.0, -TILE_SIZE / 2.0);
+            context.paint();
+            context.fill();
+            context.restore();
+        }
+        if show_violations {
+            BoardRenderer::draw_violations(context, board);
+        }
+    }
+
+    fn draw_violations(context: &cairo::Context, board: &Board) {
+        for (index, cluster) in extract_clusters(board).iter().enumerate() {
+            let (r, g, b) = CLUSTER_COLORS[index % CLUSTER_COLORS.len()];
+            context.set_source_rgba(r, g, b, 0.35);
+            for cell in &cluster.cells {
+                context.rectangle(
+                    cell.pos.x as f64 * TILE_SIZE,
+                    cell.pos.y as f64 * TILE_SIZE,
+                    TILE_SIZE,
+                    TILE_SIZE,
+                );
+                context.fill();
+            }
+        }
+        context.set_line_width(8.0);
+        context.set_source_rgb(1.0, 0.5, 0.0);
+        for edge in find_unclosed_parts(board, &TerrainType::TOWN) {
+            BoardRenderer::draw_edge(context, &edge);
+        }
+        context.set_source_rgb(1.0, 0.0, 0.0);
+        for edge in find_non_matching_edges(board) {
+            BoardRenderer::draw_edge(context, &edge);
+        }
+    }
+
+    fn draw_edge(context: &cairo::Context, edge: &Edge) {
+        let left = edge.pos.x as f64 * TILE_SIZE;
+        let top = edge.pos.y as f64 * TILE_SIZE;
+        let right = left + TILE_SIZE;
+        let bottom = top + TILE_SIZE;
+        let ((x1, y1), (x2, y2)) = match edge.side {
+            CardSide::LEFT => ((left, top), (left, bottom)),
+            CardSide::TOP => ((left, top), (right, top)),
+            CardSide::RIGHT => ((right, top), (right, bottom)),
+            CardSide::BOTTOM => ((left, bottom), (right, bottom)),
+        };
+        context.move_to(x1, y1);
+        context.line_to(x2, y2);
+        context.stroke();
+    }
+
+    /// Exports the board to a PNG or SVG image, depending on the extension of `path`.
+    pub fn export(&self, board: &Board, path: &Path, show_violations: bool) -> Result<(), String> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => self.export_png(board, path, show_violations),
+            Some("svg") => self.export_svg(board, path, show_violations),
+            _ => Err(format!(
+                "cannot export {}: use a .png or .svg file",
+                path.display()
+            )),
+        }
+    }
+
+    fn export_png(&self, board: &Board, path: &Path, show_violations: bool) -> Result<(), String> {
+        let surface = cairo::ImageSurface::create(
+            cairo::Format::ARgb32,
+            board.width() as i32 * TILE_SIZE as i32,
+            board.height() as i32 * TILE_SIZE as i32,
+        )
+        .map_err(|status| format!("cannot create image: {:?}", status))?;
+        self.draw(&cairo::Context::new(&surface), board, show_violations);
+        let mut file = std::fs::File::create(path)
+            .map_err(|error| format!("cannot create {}: {}", path.display(), error))?;
+        surface
+            .write_to_png(&mut file)
+            .map_err(|error| format!("cannot write {}: {:?}", path.display(), error))
+    }
+
+    fn export_svg(&self, board: &Board, path: &Path, show_violations: bool) -> Result<(), String> {
+        let surface = cairo::SvgSurface::new(
+            board.width() as f64 * TILE_SIZE,
+            board.height() as f64 * TILE_SIZE,
+            Some(path),
+        )
+        .map_err(|status| format!("cannot create {}: {:?}", path.display(), status))?;
+        self.draw(&cairo::Context::new(&surface), board, show_violations);
+        surface.finish();
+        match surface.status() {
+            cairo::Status::Success => Ok(()),
+            status => Err(format!("cannot write {}: {:?}", path.display(), status)),
+        }
+    }
+}
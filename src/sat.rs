@@ -0,0 +1,480 @@
+use std::collections::BinaryHeap;
+use std::io::Write;
+use std::path::Path;
+
+/// A literal in DIMACS notation: variable numbers start at 1 and negative numbers stand for
+/// negated variables.
+pub type Lit = i32;
+
+const ACTIVITY_DECAY: f64 = 0.95;
+const RESTART_BASE: usize = 100;
+
+pub enum SatResult {
+    Sat(Vec<bool>),
+    Unsat,
+    Unknown,
+}
+
+/// A small CDCL solver: two watched literals per clause, first-UIP clause learning, activity
+/// based branching with phase saving and Luby restarts. Clauses can be added between two calls
+/// to `solve`, which keeps everything learnt so far.
+pub struct SatSolver {
+    clauses: Vec<Vec<usize>>,
+    watches: Vec<Vec<usize>>,
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    trail: Vec<usize>,
+    trail_limits: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    activity_increment: f64,
+    order: BinaryHeap<(u64, usize)>,
+    phases: Vec<bool>,
+    seen: Vec<bool>,
+    restarts: usize,
+    unsat: bool,
+}
+
+impl SatSolver {
+    pub fn new(var_count: usize) -> SatSolver {
+        SatSolver {
+            clauses: vec![],
+            watches: vec![vec![]; var_count * 2],
+            values: vec![None; var_count],
+            levels: vec![0; var_count],
+            reasons: vec![None; var_count],
+            trail: vec![],
+            trail_limits: vec![],
+            propagated: 0,
+            activity: vec![0.0; var_count],
+            activity_increment: 1.0,
+            order: (0..var_count).map(|var| (0, var)).collect(),
+            phases: vec![false; var_count],
+            seen: vec![false; var_count],
+            restarts: 0,
+            unsat: false,
+        }
+    }
+
+    pub fn add_clause(&mut self, clause: &[Lit]) {
+        self.cancel_until(0);
+        let mut lits: Vec<usize> = vec![];
+        for lit in clause.iter().map(|lit| to_internal(*lit)) {
+            if lits.contains(&(lit ^ 1)) || self.value(lit) == Some(true) {
+                return;
+            }
+            if !lits.contains(&lit) && self.value(lit) != Some(false) {
+                lits.push(lit);
+            }
+        }
+        match lits.len() {
+            0 => self.unsat = true,
+            1 => {
+                self.assign(lits[0], None);
+                if self.propagate().is_some() {
+                    self.unsat = true;
+                }
+            }
+            _ => {
+                self.attach(lits);
+            }
+        }
+    }
+
+    /// Searches for a model until `conflict_limit` conflicts have been analysed.
+    pub fn solve(&mut self, conflict_limit: usize) -> SatResult {
+        if self.unsat {
+            return SatResult::Unsat;
+        }
+        let mut conflicts = 0;
+        loop {
+            let restart_limit = RESTART_BASE * luby(self.restarts);
+            let mut restart_conflicts = 0;
+            loop {
+                if let Some(conflict) = self.propagate() {
+                    conflicts += 1;
+                    restart_conflicts += 1;
+                    if self.trail_limits.is_empty() {
+                        self.unsat = true;
+                        return SatResult::Unsat;
+                    }
+                    let (learnt, level) = self.analyze(conflict);
+                    self.cancel_until(level);
+                    if learnt.len() == 1 {
+                        self.assign(learnt[0], None);
+                    } else {
+                        let asserting = learnt[0];
+                        let clause = self.attach(learnt);
+                        self.assign(asserting, Some(clause));
+                    }
+                    self.activity_increment /= ACTIVITY_DECAY;
+                    if conflicts >= conflict_limit {
+                        self.cancel_until(0);
+                        return SatResult::Unknown;
+                    }
+                } else if restart_conflicts >= restart_limit {
+                    self.cancel_until(0);
+                    self.restarts += 1;
+                    break;
+                } else {
+                    match self.pick_branch_var() {
+                        Some(var) => {
+                            self.trail_limits.push(self.trail.len());
+                            let lit = var * 2 + if self.phases[var] { 0 } else { 1 };
+                            self.assign(lit, None);
+                        }
+                        None => {
+                            let model = self.values.iter().map(|value| value.unwrap()).collect();
+                            return SatResult::Sat(model);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn attach(&mut self, lits: Vec<usize>) -> usize {
+        let index = self.clauses.len();
+        self.watches[lits[0]].push(index);
+        self.watches[lits[1]].push(index);
+        self.clauses.push(lits);
+        index
+    }
+
+    fn value(&self, lit: usize) -> Option<bool> {
+        self.values[lit / 2].map(|value| value == (lit % 2 == 0))
+    }
+
+    fn assign(&mut self, lit: usize, reason: Option<usize>) {
+        let var = lit / 2;
+        self.values[var] = Some(lit % 2 == 0);
+        self.levels[var] = self.trail_limits.len();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assigns every literal implied by the trail and returns the clause that became false, if
+    /// any. The implied literal of a reason clause is always its first one.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let false_lit = self.trail[self.propagated] ^ 1;
+            self.propagated += 1;
+            let mut watchers = std::mem::take(&mut self.watches[false_lit]);
+            let mut kept = 0;
+            let mut index = 0;
+            while index < watchers.len() {
+                let clause_index = watchers[index];
+                index += 1;
+                let clause = &mut self.clauses[clause_index];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.values[first / 2].map(|value| value == (first % 2 == 0)) == Some(true) {
+                    watchers[kept] = clause_index;
+                    kept += 1;
+                    continue;
+                }
+                let values = &self.values;
+                let replacement = (2..clause.len()).find(|k| {
+                    let lit = clause[*k];
+                    values[lit / 2].map(|value| value == (lit % 2 == 0)) != Some(false)
+                });
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    let watched = clause[1];
+                    self.watches[watched].push(clause_index);
+                    continue;
+                }
+                watchers[kept] = clause_index;
+                kept += 1;
+                if self.value(first) == Some(false) {
+                    while index < watchers.len() {
+                        watchers[kept] = watchers[index];
+                        kept += 1;
+                        index += 1;
+                    }
+                    watchers.truncate(kept);
+                    self.watches[false_lit] = watchers;
+                    return Some(clause_index);
+                }
+                self.assign(first, Some(clause_index));
+            }
+            watchers.truncate(kept);
+            self.watches[false_lit] = watchers;
+        }
+        None
+    }
+
+    /// Learns the first-UIP clause of a conflict and returns it with the level to go back to.
+    /// The asserting literal comes first and a literal of the backtrack level second.
+    fn analyze(&mut self, conflict: usize) -> (Vec<usize>, usize) {
+        let level = self.trail_limits.len();
+        let mut learnt = vec![0];
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut skip_first = false;
+        let mut index = self.trail.len();
+        loop {
+            let start = if skip_first { 1 } else { 0 };
+            for k in start..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let var = lit / 2;
+                if !self.seen[var] && self.levels[var] > 0 {
+                    self.seen[var] = true;
+                    self.bump(var);
+                    if self.levels[var] == level {
+                        pending += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index] / 2] {
+                    break;
+                }
+            }
+            let lit = self.trail[index];
+            self.seen[lit / 2] = false;
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = lit ^ 1;
+                break;
+            }
+            clause = self.reasons[lit / 2].unwrap();
+            skip_first = true;
+        }
+        for lit in &learnt[1..] {
+            self.seen[lit / 2] = false;
+        }
+        let mut backtrack_level = 0;
+        for k in 1..learnt.len() {
+            let lit_level = self.levels[learnt[k] / 2];
+            if lit_level > backtrack_level {
+                backtrack_level = lit_level;
+                learnt.swap(1, k);
+            }
+        }
+        (learnt, backtrack_level)
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.activity_increment;
+        if self.activity[var] > 1e100 {
+            for activity in self.activity.iter_mut() {
+                *activity *= 1e-100;
+            }
+            self.activity_increment *= 1e-100;
+            let activity = &self.activity;
+            self.order = (0..activity.len())
+                .map(|var| (activity[var].to_bits(), var))
+                .collect();
+        } else {
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+    }
+
+    /// The unassigned variable with the highest activity. The heap may hold outdated entries,
+    /// which are dropped on the way.
+    fn pick_branch_var(&mut self) -> Option<usize> {
+        while let Some((activity, var)) = self.order.pop() {
+            if self.values[var].is_none() && activity == self.activity[var].to_bits() {
+                return Some(var);
+            }
+        }
+        None
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.trail_limits.len() <= level {
+            return;
+        }
+        let limit = self.trail_limits[level];
+        for lit in self.trail.drain(limit..) {
+            let var = lit / 2;
+            self.phases[var] = lit % 2 == 0;
+            self.values[var] = None;
+            self.reasons[var] = None;
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+        self.trail_limits.truncate(level);
+        self.propagated = self.trail.len();
+    }
+}
+
+fn to_internal(lit: Lit) -> usize {
+    (lit.abs() as usize - 1) * 2 + if lit < 0 { 1 } else { 0 }
+}
+
+/// The Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ... for restart intervals.
+fn luby(index: usize) -> usize {
+    let mut size = 1;
+    let mut max = 1;
+    while size < index + 1 {
+        size = size * 2 + 1;
+        max *= 2;
+    }
+    let mut index = index;
+    while size - 1 != index {
+        size = (size - 1) / 2;
+        max /= 2;
+        index %= size;
+    }
+    max
+}
+
+/// Writes clauses in the DIMACS CNF format, preceded by a comment line for every entry of
+/// `comments`.
+pub fn write_dimacs(
+    path: &Path,
+    var_count: usize,
+    clauses: &Vec<Vec<Lit>>,
+    comments: &Vec<String>,
+) -> Result<(), String> {
+    let write_error = |error: std::io::Error| format!("cannot write {}: {}", path.display(), error);
+    let file = std::fs::File::create(path).map_err(write_error)?;
+    let mut writer = std::io::BufWriter::new(file);
+    for comment in comments {
+        writeln!(writer, "c {}", comment).map_err(write_error)?;
+    }
+    writeln!(writer, "p cnf {} {}", var_count, clauses.len()).map_err(write_error)?;
+    for clause in clauses {
+        for lit in clause {
+            write!(writer, "{} ", lit).map_err(write_error)?;
+        }
+        writeln!(writer, "0").map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64;
+
+    fn solver(var_count: usize, clauses: &Vec<Vec<Lit>>) -> SatSolver {
+        let mut solver = SatSolver::new(var_count);
+        for clause in clauses {
+            solver.add_clause(clause);
+        }
+        solver
+    }
+
+    fn satisfies(model: &Vec<bool>, clauses: &Vec<Vec<Lit>>) -> bool {
+        clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|lit| model[lit.abs() as usize - 1] == (*lit > 0))
+        })
+    }
+
+    fn brute_force(var_count: usize, clauses: &Vec<Vec<Lit>>) -> bool {
+        (0..1u32 << var_count).any(|bits| {
+            let model = (0..var_count).map(|var| bits & (1 << var) != 0).collect();
+            satisfies(&model, clauses)
+        })
+    }
+
+    /// Every pigeon sits in a hole and no hole holds two pigeons.
+    fn pigeonhole(pigeons: usize, holes: usize) -> Vec<Vec<Lit>> {
+        let var = |pigeon: usize, hole: usize| (pigeon * holes + hole + 1) as Lit;
+        let mut clauses = vec![];
+        for pigeon in 0..pigeons {
+            clauses.push((0..holes).map(|hole| var(pigeon, hole)).collect());
+        }
+        for hole in 0..holes {
+            for pigeon1 in 0..pigeons {
+                for pigeon2 in pigeon1 + 1..pigeons {
+                    clauses.push(vec![-var(pigeon1, hole), -var(pigeon2, hole)]);
+                }
+            }
+        }
+        clauses
+    }
+
+    #[test]
+    fn pigeonhole_with_enough_holes_is_satisfiable() {
+        let clauses = pigeonhole(6, 6);
+        match solver(36, &clauses).solve(usize::MAX) {
+            SatResult::Sat(model) => assert!(satisfies(&model, &clauses)),
+            _ => panic!("expected a model"),
+        }
+    }
+
+    #[test]
+    fn pigeonhole_with_too_few_holes_is_unsatisfiable() {
+        let clauses = pigeonhole(7, 6);
+        let mut solver = solver(42, &clauses);
+        assert!(matches!(solver.solve(usize::MAX), SatResult::Unsat));
+        assert!(solver.clauses.len() > clauses.len(), "no clause was learnt");
+        assert!(solver.restarts > 0, "the solver never restarted");
+    }
+
+    #[test]
+    fn conflict_limit_stops_the_search() {
+        let mut solver = solver(42, &pigeonhole(7, 6));
+        assert!(matches!(solver.solve(10), SatResult::Unknown));
+        assert!(matches!(solver.solve(usize::MAX), SatResult::Unsat));
+    }
+
+    #[test]
+    fn clauses_added_after_a_model_are_respected() {
+        let mut solver = solver(3, &vec![vec![1, 2, 3]]);
+        let mut clauses = vec![vec![1, 2, 3]];
+        for clause in vec![vec![-1], vec![-2], vec![-3]] {
+            assert!(matches!(solver.solve(usize::MAX), SatResult::Sat(_)));
+            solver.add_clause(&clause);
+            clauses.push(clause);
+        }
+        assert!(matches!(solver.solve(usize::MAX), SatResult::Unsat));
+        assert!(!brute_force(3, &clauses));
+    }
+
+    #[test]
+    fn random_3_sat_agrees_with_brute_force() {
+        let mut rng = Pcg64::seed_from_u64(1);
+        let var_count = 12;
+        let mut results = (0, 0);
+        for _ in 0..300 {
+            // Around 4.3 clauses per variable, where instances are as often SAT as UNSAT.
+            let clauses: Vec<Vec<Lit>> = (0..52)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| {
+                            let var = rng.gen_range(1, var_count as Lit + 1);
+                            if rng.gen() {
+                                var
+                            } else {
+                                -var
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            let expected = brute_force(var_count, &clauses);
+            match solver(var_count, &clauses).solve(usize::MAX) {
+                SatResult::Sat(model) => {
+                    assert!(expected);
+                    assert!(satisfies(&model, &clauses));
+                    results.0 += 1;
+                }
+                SatResult::Unsat => {
+                    assert!(!expected);
+                    results.1 += 1;
+                }
+                SatResult::Unknown => panic!("no conflict limit was set"),
+            }
+        }
+        assert!(results.0 > 0 && results.1 > 0, "{:?}", results);
+    }
+
+    #[test]
+    fn luby_sequence() {
+        let sequence: Vec<usize> = (0..15).map(luby).collect();
+        assert_eq!(sequence, vec![1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+}
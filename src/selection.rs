@@ -0,0 +1,61 @@
+use crate::config::{EvolutionConfig, SelectionKind};
+use crate::evolution::EvolutionRng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+/// Picks a parent from a population rated by `scores`, which are sorted from the best (lowest)
+/// score to the worst.
+pub trait Selection {
+    fn select(&self, rng: &mut EvolutionRng, scores: &Vec<usize>) -> usize;
+}
+
+struct Tournament {
+    size: usize,
+}
+
+impl Selection for Tournament {
+    fn select(&self, rng: &mut EvolutionRng, scores: &Vec<usize>) -> usize {
+        (0..self.size)
+            .map(|_| rng.gen_range(0, scores.len()))
+            .min()
+            .unwrap()
+    }
+}
+
+struct RouletteWheel;
+
+impl Selection for RouletteWheel {
+    fn select(&self, rng: &mut EvolutionRng, scores: &Vec<usize>) -> usize {
+        let weights = scores.iter().map(|score| 1.0 / (*score as f64 + 1.0));
+        WeightedIndex::new(weights).unwrap().sample(rng)
+    }
+}
+
+struct RankLinear;
+
+impl Selection for RankLinear {
+    fn select(&self, rng: &mut EvolutionRng, scores: &Vec<usize>) -> usize {
+        let weights = (0..scores.len()).map(|rank| scores.len() - rank);
+        WeightedIndex::new(weights).unwrap().sample(rng)
+    }
+}
+
+struct SquareRootRank;
+
+impl Selection for SquareRootRank {
+    fn select(&self, rng: &mut EvolutionRng, scores: &Vec<usize>) -> usize {
+        let rand: f64 = rng.gen_range(0.0, 1.0);
+        ((1.0 - ((1.0 - rand).sqrt())) * scores.len() as f64) as usize
+    }
+}
+
+pub fn selection(config: &EvolutionConfig) -> Box<dyn Selection> {
+    match config.selection {
+        SelectionKind::Tournament => Box::new(Tournament {
+            size: config.tournament_size,
+        }),
+        SelectionKind::RouletteWheel => Box::new(RouletteWheel),
+        SelectionKind::RankLinear => Box::new(RankLinear),
+        SelectionKind::SquareRootRank => Box::new(SquareRootRank),
+    }
+}
@@ -0,0 +1,92 @@
+use crate::annealing::Annealing;
+use crate::backtracking::Backtracking;
+use crate::config::{EvolutionConfig, SolverKind};
+use crate::evolution::{initial_state, seeded_rng, Evolution, EvolutionState};
+use crate::fitness::Fitness;
+use crate::island::Islands;
+use crate::model::{Board, Card};
+use crate::perfect_board::PerfectBoardSearch;
+use std::path::Path;
+
+/// A strategy that searches for a board one generation at a time. What a generation stands for
+/// depends on the solver, but each one is short enough to report progress in between.
+pub trait Solver {
+    /// Runs one more generation and returns the best board found so far.
+    fn step(&mut self) -> RatedBoard;
+
+    /// The best board found so far, once a generation has run.
+    fn best(&self) -> Option<RatedBoard>;
+
+    fn is_done(&self) -> bool;
+
+    /// Explains why the solver gave up without a board, if it did.
+    fn status(&self) -> Option<String> {
+        None
+    }
+
+    /// Saves the solver to `path` so that a later run can resume it, for solvers that support
+    /// it. `cards_path` is the card set the solver was started with.
+    fn save_checkpoint(&self, _path: &Path, _cards_path: &Path) -> Result<(), String> {
+        Err("checkpoints need the evolution solver".to_owned())
+    }
+}
+
+/// Receives the progress of a solver after every generation.
+pub trait ProgressSink {
+    fn report(&self, result: RatedBoard);
+
+    /// Called after `report` with the generation just reported, so the sink can decide to save
+    /// a checkpoint of the solver.
+    fn checkpoint(&self, _generation: usize, _solver: &dyn Solver) {}
+
+    fn status(&self, _status: &str) {}
+}
+
+#[derive(Clone)]
+pub struct RatedBoard {
+    pub generation: usize,
+    pub fitness: Fitness,
+    pub board: Board,
+}
+
+/// Creates the solver chosen in the config. `resume` continues a saved evolution instead of
+/// starting a new one; only a single island can be resumed.
+pub fn solver<'a>(
+    config: &'a EvolutionConfig,
+    cards: &'a Vec<Card>,
+    resume: Option<EvolutionState>,
+) -> Box<dyn Solver + 'a> {
+    match config.solver {
+        SolverKind::Evolution if config.island_count > 1 => {
+            Box::new(Islands::new(config, cards, &mut seeded_rng(config)))
+        }
+        SolverKind::Evolution => {
+            let state = resume.unwrap_or_else(|| initial_state(config, cards, seeded_rng(config)));
+            Box::new(Evolution::new(config, cards, state))
+        }
+        SolverKind::Backtracking => Box::new(Backtracking::new(config, cards)),
+        SolverKind::Annealing => Box::new(Annealing::new(config, cards)),
+        SolverKind::Sat => Box::new(PerfectBoardSearch::new(config, cards)),
+    }
+}
+
+/// Steps the solver until it is done, reporting every generation to the sink.
+pub fn run_solver(solver: &mut dyn Solver, sink: &dyn ProgressSink) -> RatedBoard {
+    loop {
+        let result = solver.step();
+        let generation = result.generation;
+        sink.report(result);
+        sink.checkpoint(generation, solver);
+        if solver.is_done() {
+            if let Some(status) = solver.status() {
+                sink.status(&status);
+            }
+            return solver.best().unwrap();
+        }
+    }
+}
+
+/// Runs the solver chosen in the config until it finishes or reaches the generation limit.
+pub fn solve(config: &EvolutionConfig, cards: &Vec<Card>, sink: &dyn ProgressSink) -> RatedBoard {
+    run_solver(solver(config, cards, None).as_mut(), sink)
+}